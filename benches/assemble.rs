@@ -0,0 +1,99 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use chip8_assembler::asm::generate_full_asm;
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+/// Counts every allocation so the benchmark can report how many one assembly makes
+struct CountingAlloc;
+
+static ALLOCATIONS: AtomicUsize = AtomicUsize::new(0);
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        ALLOCATIONS.fetch_add(1, Ordering::Relaxed);
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static GLOBAL: CountingAlloc = CountingAlloc;
+
+/// Generates a program with `routines` subroutines of instructions and sprite data.
+/// The output only depends on `routines`, so every run assembles the same source.
+fn generate_program(routines: usize) -> String {
+    let mut src = String::new();
+    writeln!(src, "define SPRITE_HEIGHT 5").unwrap();
+    writeln!(src, "define POS_X V1").unwrap();
+    writeln!(src, "define POS_Y V2").unwrap();
+    writeln!(src, "start:").unwrap();
+    for i in 0..routines {
+        writeln!(src, "    CALL routine_{}", i).unwrap();
+    }
+    writeln!(src, "    JP start").unwrap();
+
+    for i in 0..routines {
+        let reg = i % 15;
+        writeln!(src, "routine_{}:", i).unwrap();
+        writeln!(src, "    LD V{:X}, {:#04x}", reg, i % 256).unwrap();
+        writeln!(src, "    ADD V{:X}, 1", reg).unwrap();
+        writeln!(src, "    SE V{:X}, V{:X}", reg, (reg + 1) % 15).unwrap();
+        writeln!(src, "    LD I, sprite_{}", i).unwrap();
+        writeln!(src, "    DRW POS_X, POS_Y, SPRITE_HEIGHT").unwrap();
+        writeln!(src, "    RET").unwrap();
+        writeln!(src, "sprite_{}:", i).unwrap();
+        writeln!(
+            src,
+            "    db %11110000, %10010000, %{:08b}, %10010000, %11110000",
+            i % 256
+        )
+        .unwrap();
+        writeln!(src, "    dw {:#06x}", i).unwrap();
+        writeln!(src, "    align 2").unwrap();
+    }
+    src
+}
+
+fn bench_assemble(c: &mut Criterion) {
+    let mut group = c.benchmark_group("assemble");
+    for routines in [100, 500] {
+        let path = std::env::temp_dir().join(format!("chip8_assembler_bench_{}.asm", routines));
+        std::fs::write(&path, generate_program(routines)).unwrap();
+        let paths = vec![path.to_string_lossy().to_string()];
+        let options = AssemblerOptions::default();
+
+        let assemble = || {
+            let mut diagnostics = Diagnostics::new();
+            let full_asm = generate_full_asm(&paths, &options, &mut diagnostics);
+            let bytes = full_asm.to_bytes(&mut diagnostics);
+            assert!(!diagnostics.has_errors());
+            bytes
+        };
+
+        let before = ALLOCATIONS.load(Ordering::Relaxed);
+        let bytes = assemble();
+        let allocations = ALLOCATIONS.load(Ordering::Relaxed) - before;
+        eprintln!(
+            "{} routines: {} bytes, {} allocations per assembly",
+            routines,
+            bytes.len(),
+            allocations
+        );
+
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(routines), &routines, |b, _| {
+            b.iter(assemble)
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, bench_assemble);
+criterion_main!(benches);
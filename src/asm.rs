@@ -1,18 +1,48 @@
-use crate::instructions::Opcode;
-use std::collections::HashMap;
+use crate::diagnostics::{Diagnostic, Diagnostics};
+use crate::error::AssemblyError;
+use crate::instructions::{decode, supported_instructions, Opcode};
+use crate::options::{AssemblerOptions, ShiftQuirk, Target};
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 use std::error::Error;
 use std::fmt;
 use std::fs::File;
+use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::{BufRead, BufReader};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct ParseOperandError {
     pub message: String,
+    /// The name, when the operand is one that no label or define has
+    pub undefined: Option<String>,
+    /// Whether the operand is a number too large for where it goes
+    pub out_of_range: bool,
+    /// The operand as written, when defines were substituted into it, so the error can name them
+    pub source: Option<String>,
 }
 impl ParseOperandError {
-    fn new(message: String) -> Self {
+    pub(crate) fn new(message: String) -> Self {
         Self {
             message: message.to_string(),
+            undefined: None,
+            out_of_range: false,
+            source: None,
+        }
+    }
+
+    /// Records what the failing operand was written as, keeping one already recorded
+    pub(crate) fn with_source(mut self, source: Option<&str>) -> Self {
+        if self.source.is_none() {
+            self.source = source.map(str::to_string);
+        }
+        self
+    }
+
+    fn out_of_range(message: String) -> Self {
+        Self {
+            out_of_range: true,
+            ..Self::new(message)
         }
     }
 }
@@ -26,53 +56,146 @@ impl fmt::Display for ParseOperandError {
 #[derive(Clone)]
 pub struct Operand {
     pub repr: String,
+    /// Label, define or expression the value was substituted from, for error messages
+    pub symbol: Option<String>,
 }
 impl Operand {
     fn new(repr: String) -> Operand {
-        Operand { repr }
+        Operand { repr, symbol: None }
     }
 
-    pub fn parse_numeric_str(value: String) -> Result<u16, ParseOperandError> {
+    /// The operand as it appears in the source, before anything was substituted into it
+    pub fn written(&self) -> &str {
+        self.symbol.as_deref().unwrap_or(&self.repr)
+    }
+
+    /// Replaces the operand with the value of the name it holds, remembering the first name
+    fn substitute(&mut self, value: String) {
+        let symbol = self.symbol.take().unwrap_or(std::mem::take(&mut self.repr));
+        *self = Operand {
+            repr: value,
+            symbol: Some(symbol),
+        };
+    }
+
+    pub fn parse_numeric_str(value: &str) -> Result<u16, ParseOperandError> {
         let parsed = if value.starts_with("0x") || value.starts_with("#") {
-            u16::from_str_radix(value.trim_start_matches("0x").trim_start_matches("#"), 16)
+            u16::from_str_radix(value.trim_start_matches("0x").trim_start_matches("#"), 16).ok()
         } else if value.starts_with("%") {
-            u16::from_str_radix(value.trim_start_matches("%"), 2)
-        } else if value.starts_with('\'') && value.ends_with('\'') {
-            Ok(value.chars().nth(1).unwrap() as u16)
+            u16::from_str_radix(value.trim_start_matches("%"), 2).ok()
+        } else if value.len() >= 2 && value.starts_with('\'') && value.ends_with('\'') {
+            return Operand::parse_char_literal(&value[1..value.len() - 1]);
         } else {
-            value.parse::<u16>()
+            value.parse::<u16>().ok()
         };
 
         match parsed {
-            Ok(n) => Ok(n),
-            Err(_) => Err(ParseOperandError::new(format!("Invalid number: {}", value))),
+            Some(n) => Ok(n),
+            // Labels and defines are substituted before parsing, so a name left here was never defined
+            None if value.starts_with(|c: char| c.is_alphabetic() || c == '_') => {
+                Err(ParseOperandError {
+                    undefined: Some(value.to_string()),
+                    ..ParseOperandError::new(format!("Undefined label or define: {}", value))
+                })
+            }
+            None => Err(ParseOperandError::new(format!("Invalid number: {}", value))),
         }
     }
 
-    pub fn parse_register_str(value: String) -> Result<u16, ParseOperandError> {
-        let parsed =
-            u16::from_str_radix(value.trim_start_matches("V").trim_start_matches("v"), 16).unwrap();
+    /// Value of a character literal given without its quotes. Besides a single character it can
+    /// be an escape: `\0`, `\t`, `\n`, `\r`, `\\` or `\xNN` for any byte.
+    fn parse_char_literal(inner: &str) -> Result<u16, ParseOperandError> {
+        let invalid =
+            || ParseOperandError::new(format!("Invalid escape in char literal: '{}'", inner));
+        let mut chars = inner.chars();
+        let value = match (chars.next(), chars.next()) {
+            (Some('\\'), Some(escape)) => match (escape, chars.as_str()) {
+                ('0', "") => 0,
+                ('t', "") => 0x09,
+                ('n', "") => 0x0A,
+                ('r', "") => 0x0D,
+                ('\\', "") => 0x5C,
+                ('x', hex) if hex.len() == 2 => {
+                    u16::from_str_radix(hex, 16).map_err(|_| invalid())?
+                }
+                _ => return Err(invalid()),
+            },
+            (Some(c), None) if c.is_ascii() => c as u16,
+            (Some(c), None) => {
+                return Err(ParseOperandError::new(format!(
+                    "char literal must be ASCII, found '{}' (U+{:04X})",
+                    c, c as u32
+                )))
+            }
+            (None, _) => return Err(ParseOperandError::new("Empty char literal: ''".to_string())),
+            (Some(_), Some(_)) => {
+                return Err(ParseOperandError::new(format!(
+                    "char literal must be one character: '{}'",
+                    inner
+                )))
+            }
+        };
+        Ok(value)
+    }
+
+    /// Parses a `db` value, accepting 0 to 255 or a negative number down to -128 stored as
+    /// two's complement
+    pub fn parse_byte_str(value: &str) -> Result<u8, ParseOperandError> {
+        Operand::byte_from(evaluate(value, &HashMap::new())?, value)
+    }
 
-        if parsed <= 15 {
-            Ok(parsed)
-        } else {
-            Err(ParseOperandError::new(format!(
-                "Invalid register number: {}",
-                parsed
-            )))
+    /// Narrows an evaluated `db` value to a byte, `repr` is the source text used in errors
+    pub(crate) fn byte_from(value: i32, repr: &str) -> Result<u8, ParseOperandError> {
+        match value {
+            0..=255 => Ok(value as u8),
+            -128..=-1 => Ok(value as i8 as u8),
+            _ => Err(ParseOperandError::out_of_range(format!(
+                "Value does not fit in a byte: {}",
+                repr
+            ))),
         }
     }
 
+    /// Parses a register, which must be exactly `V0` to `VF` in either case
+    pub fn parse_register_str(value: &str) -> Result<u16, ParseOperandError> {
+        if !Operand::is_register_name(value) {
+            return Err(ParseOperandError::new(format!(
+                "Invalid register '{}', expected V0 to VF",
+                value
+            )));
+        }
+        Ok(u16::from_str_radix(&value[1..], 16).unwrap())
+    }
+
+    /// Whether `value` is exactly a register name, `V0` to `VF` in either case
+    pub fn is_register_name(value: &str) -> bool {
+        let mut chars = value.chars();
+        matches!(chars.next(), Some('v' | 'V'))
+            && matches!(chars.next(), Some(c) if c.is_ascii_hexdigit())
+            && chars.next().is_none()
+    }
+
     pub fn is_register(&self) -> bool {
-        self.repr.starts_with("v") || self.repr.starts_with("V")
+        Operand::is_register_name(&self.repr)
     }
 
-    pub fn parse(self) -> Result<u16, ParseOperandError> {
-        if self.is_register() {
-            Operand::parse_register_str(self.repr)
+    /// Whether the operand looks like a mistyped register, such as `Vv1` or `V10`, rather than
+    /// a name or number
+    pub fn is_malformed_register(&self) -> bool {
+        let mut chars = self.repr.chars();
+        matches!(chars.next(), Some('v' | 'V'))
+            && (2..=3).contains(&self.repr.len())
+            && chars.all(|c| c.is_ascii_alphanumeric())
+            && !self.is_register()
+    }
+
+    pub fn parse(&self) -> Result<u16, ParseOperandError> {
+        let parsed = if self.is_register() || self.is_malformed_register() {
+            Operand::parse_register_str(&self.repr)
         } else {
-            Operand::parse_numeric_str(self.repr)
-        }
+            Operand::parse_numeric_str(&self.repr)
+        };
+        parsed.map_err(|e| e.with_source(self.symbol.as_deref()))
     }
 }
 impl fmt::Display for Operand {
@@ -90,6 +213,7 @@ pub trait Asm {
     fn get_byte_size(&self) -> usize;
     fn from_line(line: String) -> Self;
 }
+#[derive(Clone)]
 pub enum AsmEnum {
     Instruction(Instruction),
     Label(Label),
@@ -97,6 +221,15 @@ pub enum AsmEnum {
     Directive(Directive),
 }
 impl AsmEnum {
+    /// Name of the label the entry defines, either `name:` or `here name`
+    pub fn label_name(&self) -> Option<&str> {
+        match self {
+            AsmEnum::Label(l) => Some(&l.name),
+            AsmEnum::Directive(d) if d.mnemonic == "here" => d.args.first().map(|a| a.as_str()),
+            _ => None,
+        }
+    }
+
     fn get_byte_size(&self) -> usize {
         match self {
             AsmEnum::Instruction(i) => i.get_byte_size(),
@@ -105,6 +238,39 @@ impl AsmEnum {
             AsmEnum::Directive(d) => d.get_byte_size(),
         }
     }
+
+    /// Byte size when placed at `address`, which some directives (align) depend on
+    fn get_byte_size_at(&self, address: usize, labels: &HashMap<String, usize>) -> usize {
+        match self {
+            AsmEnum::Directive(d) => d.get_byte_size_at(address, labels),
+            _ => self.get_byte_size(),
+        }
+    }
+}
+impl AsmEnum {
+    /// The entry written back as a line of source
+    pub fn to_source(&self) -> String {
+        match self {
+            AsmEnum::Label(l) => format!("{}:", l.name),
+            AsmEnum::Define(d) => match d.kind {
+                Some(kind) => format!("define {} {} {}", kind.name(), d.key, d.value),
+                None => format!("define {} {}", d.key, d.value),
+            },
+            AsmEnum::Directive(d) if d.mnemonic.eq_ignore_ascii_case("text") => {
+                let args: Vec<String> = d.args.iter().map(|a| format!("\"{}\"", a)).collect();
+                format!("    {} {}", d.mnemonic, args.join(", "))
+            }
+            AsmEnum::Directive(d) => format!("    {} {}", d.mnemonic, d.args.join(", "))
+                .trim_end()
+                .to_string(),
+            AsmEnum::Instruction(i) => {
+                let args: Vec<String> = i.args.iter().map(|a| a.repr.clone()).collect();
+                format!("    {} {}", i.mnemonic, args.join(", "))
+                    .trim_end()
+                    .to_string()
+            }
+        }
+    }
 }
 impl fmt::Display for AsmEnum {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -129,11 +295,99 @@ impl Instruction {
             args: args.into_iter().map(Operand::new).collect(),
         }
     }
+
+    /// V registers an instruction reads and writes, by register number
+    pub fn register_roles(&self) -> (BTreeSet<u16>, BTreeSet<u16>) {
+        let mnemonic = self.mnemonic.to_uppercase();
+        let registers: Vec<Option<u16>> = self
+            .args
+            .iter()
+            .map(|a| a.is_register().then(|| a.parse().ok()).flatten())
+            .collect();
+        let keyword = |i: usize, name: &str| {
+            self.args
+                .get(i)
+                .is_some_and(|a| a.repr.eq_ignore_ascii_case(name))
+        };
+        let (mut reads, mut writes) = (BTreeSet::new(), BTreeSet::new());
+
+        // Register ranges V0 to Vx, loaded from or stored to memory or the flag registers. Any
+        // other operand, such as the `audio` of `LD [I], audio`, touches no register.
+        let register = |i: usize| registers.get(i).copied().flatten();
+        if mnemonic == "LD" && (keyword(0, "[I]") || keyword(0, "R")) {
+            reads.extend(register(1).map(|last| 0..=last).into_iter().flatten());
+            return (reads, writes);
+        }
+        if mnemonic == "LD" && (keyword(1, "[I]") || keyword(1, "R")) {
+            writes.extend(register(0).map(|last| 0..=last).into_iter().flatten());
+            return (reads, writes);
+        }
+        // XO-CHIP's `LD Vx, Vy, I` loads the registers Vx to Vy, in either order, from memory at I
+        // and `LD I, Vx, Vy` saves them there
+        let range = |a: usize, b: usize| {
+            let (x, y) = (register(a)?, register(b)?);
+            Some(x.min(y)..=x.max(y))
+        };
+        if mnemonic == "LD" && keyword(2, "I") {
+            writes.extend(range(0, 1).into_iter().flatten());
+            return (reads, writes);
+        }
+        if mnemonic == "LD" && keyword(0, "I") && self.args.len() == 3 {
+            reads.extend(range(1, 2).into_iter().flatten());
+            return (reads, writes);
+        }
+
+        // Whether the first operand is written, and whether it is read as well
+        let (writes_first, reads_first) = match mnemonic.as_str() {
+            "LD" | "MOV" | "RND" => (true, false),
+            "ADD" | "OR" | "AND" | "XOR" | "SUB" | "SUBN" | "SHR" | "SHL" => (true, true),
+            _ => (false, true),
+        };
+        for (i, register) in registers.iter().enumerate() {
+            let Some(register) = *register else { continue };
+            let written = writes_first && i == 0;
+            if written {
+                writes.insert(register);
+            }
+            if !written || (i == 0 && reads_first) {
+                reads.insert(register);
+            }
+        }
+        if self.sets_flag() {
+            writes.insert(0xF);
+        }
+        (reads, writes)
+    }
+
+    /// Whether the instruction overwrites VF with a carry, borrow, shifted out bit or sprite
+    /// collision
+    pub fn sets_flag(&self) -> bool {
+        match self.mnemonic.to_uppercase().as_str() {
+            "SUB" | "SUBN" | "SHR" | "SHL" | "DRW" => true,
+            "ADD" => self.args.len() == 2 && self.args.iter().all(|a| a.is_register()),
+            _ => false,
+        }
+    }
+
+    /// XO-CHIP's `LD I, LONG nnnn` is followed by a full 16-bit address word
+    fn is_long(&self) -> bool {
+        self.mnemonic.eq_ignore_ascii_case("LD")
+            && self.args.len() == 3
+            && self.args[0].repr.eq_ignore_ascii_case("I")
+            && self.args[1].repr.eq_ignore_ascii_case("LONG")
+    }
 }
 impl Asm for Instruction {
     fn get_byte_size(&self) -> usize {
-        if !self.mnemonic.chars().next().unwrap().is_alphanumeric() {
+        if !self
+            .mnemonic
+            .chars()
+            .next()
+            .is_some_and(|c| c.is_alphanumeric())
+        {
             0
+        } else if self.is_long() {
+            4
         } else {
             2
         }
@@ -141,17 +395,11 @@ impl Asm for Instruction {
 
     fn from_line(line: String) -> Instruction {
         // The mnemonic is the first word separated by whitespace
-        // All other args are separated by commas
-        let split: Vec<&str> = line.split_whitespace().collect();
-        let mnemonic = split[0].to_string();
-        let args: Vec<String> = split[1..]
-            .join(",")
-            .split(',')
-            .map(|s| s.trim().to_string())
-            .filter(|s| !s.is_empty())
-            .collect();
+        // All other args are separated by commas and/or whitespace
+        let (mnemonic, rest) = split_mnemonic(&line);
+        let args = join_indirect(split_operands(rest));
 
-        Instruction::new(mnemonic, args)
+        Instruction::new(mnemonic.to_string(), join_operators(args))
     }
 }
 impl fmt::Display for Instruction {
@@ -170,8 +418,9 @@ impl fmt::Display for Instruction {
     }
 }
 
+#[derive(Clone)]
 pub struct Label {
-    name: String,
+    pub name: String,
 }
 impl Label {
     fn new(name: String) -> Label {
@@ -194,13 +443,64 @@ impl fmt::Display for Label {
     }
 }
 
+/// What a typed define, `define KIND NAME VALUE`, promises its value is
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DefineKind {
+    Register,
+    Nibble,
+    Byte,
+    Address,
+    Word,
+}
+impl DefineKind {
+    pub fn from_name(name: &str) -> Option<DefineKind> {
+        match name.to_lowercase().as_str() {
+            "reg" => Some(DefineKind::Register),
+            "nibble" => Some(DefineKind::Nibble),
+            "byte" => Some(DefineKind::Byte),
+            "addr" => Some(DefineKind::Address),
+            "word" => Some(DefineKind::Word),
+            _ => None,
+        }
+    }
+
+    /// Name as written in a typed define
+    pub fn name(&self) -> &'static str {
+        match self {
+            DefineKind::Register => "reg",
+            DefineKind::Nibble => "nibble",
+            DefineKind::Byte => "byte",
+            DefineKind::Address => "addr",
+            DefineKind::Word => "word",
+        }
+    }
+
+    /// Largest value a numeric kind allows, None for registers
+    fn max(&self) -> Option<i32> {
+        match self {
+            DefineKind::Register => None,
+            DefineKind::Nibble => Some(0xF),
+            DefineKind::Byte => Some(0xFF),
+            DefineKind::Address => Some(0xFFF),
+            DefineKind::Word => Some(0xFFFF),
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Define {
-    key: String,
-    value: String,
+    pub key: String,
+    pub value: String,
+    /// Set by the typed form, `define byte LIMIT 200`
+    pub kind: Option<DefineKind>,
 }
 impl Define {
     fn new(key: String, value: String) -> Define {
-        Define { key, value }
+        Define {
+            key,
+            value,
+            kind: None,
+        }
     }
 }
 impl Asm for Define {
@@ -210,9 +510,13 @@ impl Asm for Define {
 
     fn from_line(line: String) -> Define {
         let split: Vec<&str> = line.split_whitespace().collect();
-        let key = split[1].to_string();
-        let value = split[2].to_string();
-        Define::new(key, value)
+        match split[..] {
+            [_, kind, key, value] => Define {
+                kind: DefineKind::from_name(kind),
+                ..Define::new(key.to_string(), value.to_string())
+            },
+            _ => Define::new(split[1].to_string(), split[2].to_string()),
+        }
     }
 }
 impl fmt::Display for Define {
@@ -221,49 +525,358 @@ impl fmt::Display for Define {
     }
 }
 
+/// The standard 4x5 hex digit sprites 0-F emitted by `font`
+const FONT: [u8; 80] = [
+    0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0, 0xF0,
+    0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0, 0xF0, 0x80,
+    0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0, 0xF0, 0x90, 0xF0,
+    0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0, 0xF0, 0x80, 0x80, 0x80,
+    0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0x80,
+];
+
+/// The Super-CHIP 8x10 decimal digit sprites 0-9 emitted by `bigfont`
+const BIG_FONT: [u8; 100] = [
+    0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18, 0x18,
+    0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF, 0x3C, 0x7E,
+    0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66, 0xC6, 0xFF, 0xFF,
+    0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C, 0x3E, 0x7C, 0xC0, 0xC0,
+    0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C, 0x18, 0x30, 0x60, 0x60, 0x60,
+    0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C, 0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F,
+    0x03, 0x03, 0x3E, 0x7C,
+];
+
+/// Splits a line into its first word and the rest
+fn split_mnemonic(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], &line[end..]),
+        None => (line, ""),
+    }
+}
+
+/// Splits operands on commas and any whitespace, so `V0,\t5` and `V0 , 5` read the same. Double
+/// quoted strings are kept whole without their quotes, even when empty, and character literals
+/// such as `' '` are kept whole with theirs.
+fn split_operands(text: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut quote: Option<char> = None;
+    // Whether the current argument was quoted, so `""` is still an argument
+    let mut quoted = false;
+    for c in text.chars() {
+        match (quote, c) {
+            (None, '"') | (Some('"'), '"') => {
+                quote = if quote.is_none() { Some('"') } else { None };
+                quoted = true;
+            }
+            (None, '\'') | (Some('\''), '\'') => {
+                quote = if quote.is_none() { Some('\'') } else { None };
+                current.push(c);
+            }
+            (None, c) if c == ',' || c.is_whitespace() => {
+                if !current.is_empty() || quoted {
+                    args.push(std::mem::take(&mut current));
+                    quoted = false;
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() || quoted {
+        args.push(current);
+    }
+    args
+}
+
+/// Joins an indirect operand split on its inner spaces, such as `[ I ]`, back into one argument,
+/// written as `[I]` whatever its case and spacing
+fn join_indirect(args: Vec<String>) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::new();
+    for arg in args {
+        match joined.last_mut() {
+            Some(last) if last.starts_with('[') && !last.ends_with(']') => last.push_str(&arg),
+            _ => joined.push(arg),
+        }
+    }
+    for arg in joined.iter_mut() {
+        if arg.eq_ignore_ascii_case("[I]") {
+            *arg = "[I]".to_string();
+        }
+    }
+    joined
+}
+
+/// Joins arguments split around a lone `+` or `-` back into one expression, so `end - start`
+/// stays a single argument
+fn join_operators(args: Vec<String>) -> Vec<String> {
+    let mut joined: Vec<String> = Vec::new();
+    let mut pending_operator = false;
+    for arg in args {
+        let is_operator = arg == "+" || arg == "-";
+        match joined.last_mut() {
+            Some(last) if is_operator || pending_operator => {
+                last.push(' ');
+                last.push_str(&arg);
+            }
+            _ => joined.push(arg),
+        }
+        pending_operator = is_operator && !joined.is_empty();
+    }
+    joined
+}
+
+/// Splits an expression such as `end - start + 1` into its terms, each with the sign it is
+/// applied with. A plain value is a single positive term.
+pub(crate) fn expression_terms(expr: &str) -> Vec<(bool, &str)> {
+    let mut terms = Vec::new();
+    let mut negative = false;
+    let mut start = 0;
+    let mut in_char = false;
+    for (i, c) in expr.char_indices() {
+        match c {
+            '\'' => in_char = !in_char,
+            '+' | '-' if !in_char && !expr[start..i].trim().is_empty() => {
+                terms.push((negative, expr[start..i].trim()));
+                negative = c == '-';
+                start = i + 1;
+            }
+            _ => {}
+        }
+    }
+    terms.push((negative, expr[start..].trim()));
+    terms
+}
+
+/// Rebuilds an expression with every term found in `map` replaced, returning the new expression
+/// and the terms that were replaced
+fn substitute_terms(expr: &str, map: &HashMap<String, String>) -> (String, Vec<String>) {
+    let mut rebuilt = String::new();
+    let mut replaced = Vec::new();
+    for (i, (negative, term)) in expression_terms(expr).into_iter().enumerate() {
+        if i > 0 {
+            rebuilt.push_str(if negative { " - " } else { " + " });
+        }
+        match map.get(term) {
+            Some(value) => {
+                rebuilt.push_str(value);
+                replaced.push(term.to_string());
+            }
+            None => rebuilt.push_str(term),
+        }
+    }
+    (rebuilt, replaced)
+}
+
+/// Evaluates an expression of numbers and labels added and subtracted together
+pub(crate) fn evaluate(
+    expr: &str,
+    labels: &HashMap<String, usize>,
+) -> Result<i32, ParseOperandError> {
+    if let Some(address) = labels.get(expr) {
+        return Ok(*address as i32);
+    }
+    let mut total: i32 = 0;
+    for (negative, term) in expression_terms(expr) {
+        let (negative, term) = match term.strip_prefix('-') {
+            Some(rest) => (!negative, rest.trim()),
+            None => (negative, term),
+        };
+        let value = match labels.get(term) {
+            Some(address) => *address as i32,
+            None => Operand::parse_numeric_str(term)? as i32,
+        };
+        total = if negative {
+            total - value
+        } else {
+            total + value
+        };
+    }
+    Ok(total)
+}
+
+/// Splits a `times` count such as `(ROWS + 1) * 2` into its numbers, names, operators and
+/// brackets
+fn count_tokens(expr: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut start = None;
+    for (i, c) in expr.char_indices() {
+        let operator = matches!(c, '+' | '-' | '*' | '/' | '(' | ')');
+        if operator || c.is_whitespace() {
+            if let Some(at) = start.take() {
+                tokens.push(&expr[at..i]);
+            }
+            if operator {
+                tokens.push(&expr[i..i + 1]);
+            }
+        } else if start.is_none() {
+            start = Some(i);
+        }
+    }
+    if let Some(at) = start {
+        tokens.push(&expr[at..]);
+    }
+    tokens
+}
+
+/// The names a `times` count uses, which are defines when it is valid
+fn count_names(expr: &str) -> impl Iterator<Item = &str> {
+    count_tokens(expr)
+        .into_iter()
+        .filter(|t| t.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_'))
+}
+
+/// Evaluates a `times` count, which unlike operands can also multiply, divide and use brackets.
+/// None when it is malformed, overflows or divides by zero.
+fn evaluate_count(expr: &str, defines: &HashMap<String, usize>) -> Option<i64> {
+    // Each level parses a run of the level below it joined by its own operators
+    fn sum(tokens: &[&str], at: &mut usize, defines: &HashMap<String, usize>) -> Option<i64> {
+        let mut total = product(tokens, at, defines)?;
+        while let Some(&op @ ("+" | "-")) = tokens.get(*at) {
+            *at += 1;
+            let value = product(tokens, at, defines)?;
+            total = match op {
+                "+" => total.checked_add(value)?,
+                _ => total.checked_sub(value)?,
+            };
+        }
+        Some(total)
+    }
+    fn product(tokens: &[&str], at: &mut usize, defines: &HashMap<String, usize>) -> Option<i64> {
+        let mut total = factor(tokens, at, defines)?;
+        while let Some(&op @ ("*" | "/")) = tokens.get(*at) {
+            *at += 1;
+            let value = factor(tokens, at, defines)?;
+            total = match op {
+                "*" => total.checked_mul(value)?,
+                _ => total.checked_div(value)?,
+            };
+        }
+        Some(total)
+    }
+    fn factor(tokens: &[&str], at: &mut usize, defines: &HashMap<String, usize>) -> Option<i64> {
+        let token = *tokens.get(*at)?;
+        *at += 1;
+        match token {
+            "-" => factor(tokens, at, defines)?.checked_neg(),
+            "(" => {
+                let value = sum(tokens, at, defines)?;
+                (tokens.get(*at) == Some(&")")).then(|| *at += 1)?;
+                Some(value)
+            }
+            name => match defines.get(name) {
+                Some(value) => Some(*value as i64),
+                // Decimal counts may be too large for an operand, to be reported as such
+                None => name
+                    .parse()
+                    .ok()
+                    .or_else(|| Operand::parse_numeric_str(name).ok().map(i64::from)),
+            },
+        }
+    }
+
+    let tokens = count_tokens(expr);
+    let mut at = 0;
+    sum(&tokens, &mut at, defines).filter(|_| at == tokens.len())
+}
+
+#[derive(Clone)]
 pub struct Directive {
-    mnemonic: String,
-    args: Vec<String>,
+    pub mnemonic: String,
+    pub args: Vec<String>,
+    /// What each argument was written as before defines were substituted into it, `None` for
+    /// the ones left as they were
+    pub sources: Vec<Option<String>>,
 }
 impl Directive {
-    const VALID_DIRECTIVES: [&'static str; 4] = ["db", "dw", "text", "offset"];
+    pub const VALID_DIRECTIVES: [&'static str; 12] = [
+        "db", "dn", "dw", "text", "offset", "align", "font", "bigfont", "sprite16", "audio",
+        "here", "section",
+    ];
 
     fn new(mnemonic: String, args: Vec<String>) -> Directive {
-        Directive { mnemonic, args }
+        Directive {
+            mnemonic,
+            args,
+            sources: Vec::new(),
+        }
+    }
+
+    /// What argument `index` was written as, before any defines were substituted into it
+    fn source(&self, index: usize) -> Option<&str> {
+        self.sources
+            .get(index)
+            .and_then(|s| s.as_deref())
+            .or(self.args.get(index).map(|a| a.as_str()))
+    }
+
+    /// The only argument of `offset` and `align`, empty when it is missing
+    fn first_arg(&self) -> &str {
+        self.args.first().map_or("", |a| a.as_str())
+    }
+
+    /// Parses a numeric argument, which may also name labels resolved by the first pass or add
+    /// and subtract them, such as `end - start`
+    fn parse_arg(arg: &str, labels: &HashMap<String, usize>) -> Result<u16, ParseOperandError> {
+        match evaluate(arg, labels)? {
+            n @ 0..=0xFFFF => Ok(n as u16),
+            _ => Err(ParseOperandError::out_of_range(format!(
+                "Value out of range: {}",
+                arg
+            ))),
+        }
+    }
+
+    /// Address given to `section NAME at ADDR`
+    fn section_base(&self, labels: &HashMap<String, usize>) -> Result<usize, ParseOperandError> {
+        let base = self.args.get(2).map_or("", |a| a.as_str());
+        Directive::parse_arg(base, labels).map(|base| base as usize)
+    }
+
+    fn get_byte_size_at(&self, address: usize, labels: &HashMap<String, usize>) -> usize {
+        match self.mnemonic.to_lowercase().as_str() {
+            // Unparsable arguments are reported when the bytes are emitted
+            "offset" => Directive::parse_arg(self.first_arg(), labels).unwrap_or(0) as usize,
+            // Pads up to the base, a section starting too early is reported when emitted
+            "section" => self
+                .section_base(labels)
+                .map_or(0, |base| base.saturating_sub(address)),
+            "align" => match Directive::parse_arg(self.first_arg(), labels).unwrap_or(0) as usize {
+                0 => 0,
+                n => (n - address % n) % n,
+            },
+            _ => self.get_byte_size(),
+        }
     }
 }
 impl Asm for Directive {
     fn get_byte_size(&self) -> usize {
         match self.mnemonic.to_lowercase().as_str() {
             "db" => self.args.len(),
+            // Two nibbles per byte, an odd one out gets a byte of its own
+            "dn" => self.args.len().div_ceil(2),
             "dw" => self.args.len() * 2,
-            "text" => self.args[0].len() + 1,
-            "offset" => Operand::parse_numeric_str(self.args[0].clone()).unwrap() as usize,
+            "text" => self.args.iter().map(|a| a.chars().count() + 1).sum(),
+            "font" => FONT.len(),
+            "bigfont" => BIG_FONT.len(),
+            // 16 rows of 16 pixels
+            "sprite16" => 32,
+            // The XO-CHIP audio pattern buffer
+            "audio" => 16,
+            // Padding depends on placement, see get_byte_size_at
+            "offset" | "align" => self.get_byte_size_at(0, &HashMap::new()),
+            "section" => 0,
             _ => 0,
         }
     }
 
     fn from_line(line: String) -> Directive {
-        let split: Vec<&str> = line.split_whitespace().collect();
-        let mnemonic = split[0].to_string();
-        let remaining = split[1..].join(" ");
-
-        // Get args, grouping things in quotes together
-        let mut args: Vec<String> = Vec::new();
-        let mut in_quotes = false;
-        let mut current_arg = String::new();
-        for c in remaining.chars() {
-            if c == '\"' {
-                in_quotes = !in_quotes;
-            } else if (c == ',' || c == ' ') && !current_arg.is_empty() && !in_quotes {
-                args.push(current_arg.clone().as_str().trim().to_string());
-                current_arg = String::new();
-            } else {
-                current_arg.push(c);
-            }
-        }
-        args.push(current_arg.clone().as_str().trim().to_string());
+        let (mnemonic, rest) = split_mnemonic(&line);
+        let mnemonic = mnemonic.to_string();
+        let mut args = split_operands(rest);
 
+        if !mnemonic.eq_ignore_ascii_case("text") {
+            args = join_operators(args);
+        }
         Directive::new(mnemonic, args)
     }
 }
@@ -279,67 +892,708 @@ impl fmt::Display for Directive {
     }
 }
 
+/// Location of an entry in the source, lines start at 1
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceSpan {
+    pub file: String,
+    pub line: usize,
+    /// The line as written, for showing it under a message
+    pub text: Arc<str>,
+}
+impl fmt::Display for SourceSpan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SymbolKind {
+    Label,
+    Define,
+}
+impl fmt::Display for SymbolKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SymbolKind::Label => write!(f, "label"),
+            SymbolKind::Define => write!(f, "define"),
+        }
+    }
+}
+
+/// Where a label or define was declared and every entry that referenced it
+pub struct Symbol {
+    pub kind: SymbolKind,
+    pub defined_at: SourceSpan,
+    pub references: Vec<SourceSpan>,
+}
+
+/// One entry of a finished assembly, as yielded by `Assembly::iter_resolved`
+pub struct ResolvedEntry<'a> {
+    /// Address the entry is placed at
+    pub offset: usize,
+    /// The entry with defines and labels substituted
+    pub kind: &'a AsmEnum,
+    pub source_span: &'a SourceSpan,
+    /// What the entry emitted, empty for labels and defines
+    pub bytes: &'a [u8],
+}
+
+/// Assembly happens in two passes. The first (`update_offsets`) lays out every entry and resolves
+/// the label addresses, which are then substituted into the operands once. The second
+/// (`to_bytes`) only reads the result, so it can be called any number of times.
 pub struct Assembly {
-    pub instructions: Vec<(AsmEnum, usize)>,
+    pub instructions: Vec<(AsmEnum, usize, SourceSpan)>,
+    /// Paths of every source file that was read, including includes
+    pub files: Vec<String>,
+    /// Every label and define by name, recorded as they are substituted
+    pub symbols: BTreeMap<String, Symbol>,
+    pub options: AssemblerOptions,
+    labels: HashMap<String, usize>,
 }
 impl Assembly {
-    fn new(instructions: Vec<AsmEnum>, offset: usize) -> Assembly {
-        let instructions = instructions.into_iter().map(|i| (i, 0)).collect();
-        let mut new = Assembly { instructions };
+    /// Upper bound on layout passes, in case sizes and label addresses never settle
+    const MAX_LAYOUT_PASSES: usize = 16;
+
+    pub(crate) fn new(
+        instructions: Vec<(AsmEnum, SourceSpan)>,
+        files: Vec<String>,
+        options: AssemblerOptions,
+    ) -> Assembly {
+        let instructions = instructions
+            .into_iter()
+            .map(|(i, span)| (i, 0, span))
+            .collect();
+        let mut new = Assembly {
+            instructions,
+            files,
+            symbols: BTreeMap::new(),
+            options,
+            labels: HashMap::new(),
+        };
         new.update_defines();
-        new.update_offsets(offset);
+        new.update_offsets();
+        new.update_labels();
         new
     }
 
-    fn update_offsets(&mut self, offset: usize) {
-        let mut byte_offset = 0;
-        for (i, off) in self.instructions.iter_mut() {
-            let byte_size = i.get_byte_size();
-            *off = byte_offset + offset;
-            byte_offset += byte_size;
+    /// Size of the entry at `index` in instructions, as laid out
+    pub fn entry_size(&self, index: usize) -> usize {
+        let (entry, offset, _) = &self.instructions[index];
+        entry.get_byte_size_at(*offset, &self.labels)
+    }
+
+    /// Address a JP or CALL instruction goes to, when it can be worked out
+    pub fn jump_target(inst: &Instruction) -> Option<usize> {
+        let mnemonic = inst.mnemonic.to_uppercase();
+        if mnemonic != "JP" && mnemonic != "CALL" {
+            return None;
         }
+        inst.args.last()?.parse().ok().map(|target| target as usize)
     }
 
-    fn update_labels(&mut self) {
-        let mut label_map: HashMap<String, usize> = HashMap::new();
-        for (i, off) in self.instructions.iter() {
-            if let AsmEnum::Label(l) = i {
-                label_map.insert(l.name.clone(), *off);
+    /// Splits the code into basic blocks keyed by their first address, each holding the indices
+    /// into instructions of its instructions. Blocks start at labels and jump targets and end
+    /// after any jump, call, skip or return.
+    pub fn basic_blocks(&self) -> BTreeMap<usize, Vec<usize>> {
+        let mut leaders: BTreeSet<usize> = BTreeSet::new();
+        let mut code: Vec<(usize, usize)> = Vec::new();
+        for (index, (entry, offset, _)) in self.instructions.iter().enumerate() {
+            match entry {
+                AsmEnum::Label(_) => {
+                    leaders.insert(*offset);
+                }
+                AsmEnum::Instruction(inst) => {
+                    leaders.extend(Self::jump_target(inst));
+                    if matches!(
+                        inst.mnemonic.to_uppercase().as_str(),
+                        "JP" | "CALL" | "RET" | "EXIT" | "SE" | "SNE" | "SKP" | "SKNP"
+                    ) {
+                        leaders.insert(offset + self.entry_size(index));
+                    }
+                    code.push((*offset, index));
+                }
+                _ => {}
+            }
+        }
+
+        let mut blocks: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        let mut current = None;
+        for (offset, index) in code {
+            if current.is_none() || leaders.contains(&offset) {
+                current = Some(offset);
+            }
+            blocks.entry(current.unwrap()).or_default().push(index);
+        }
+        blocks
+    }
+
+    /// Walks every entry in order with its address, source and the slice of `bytes` (the output
+    /// of `to_bytes`) it emitted. The slice is empty when `bytes` is cut short by errors.
+    pub fn iter_resolved<'a>(
+        &'a self,
+        bytes: &'a [u8],
+    ) -> impl Iterator<Item = ResolvedEntry<'a>> + 'a {
+        self.instructions
+            .iter()
+            .enumerate()
+            .map(move |(index, (entry, offset, span))| {
+                let start = offset - self.options.offset;
+                ResolvedEntry {
+                    offset: *offset,
+                    kind: entry,
+                    source_span: span,
+                    bytes: bytes
+                        .get(start..start + self.entry_size(index))
+                        .unwrap_or_default(),
+                }
+            })
+    }
+
+    /// Decodes every emitted instruction in `bytes` and encodes it again, failing with the
+    /// address of the first one that does not come back as the same bytes
+    pub fn verify(&self, bytes: &[u8]) -> Result<(), String> {
+        let hex = |bytes: &[u8]| {
+            bytes
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect::<String>()
+        };
+        for entry in self.iter_resolved(bytes) {
+            if !matches!(entry.kind, AsmEnum::Instruction(_)) {
+                continue;
+            }
+            let (offset, original) = (entry.offset, entry.bytes);
+            if original.is_empty() {
+                return Err(format!("{:#06x}: past the end of the output", offset));
+            }
+            let (text, _) = decode(original, self.options.target)
+                .ok_or_else(|| format!("{:#06x}: {} does not decode", offset, hex(original)))?;
+            let decoded = Instruction::from_line(text.clone());
+            let reencoded = Opcode::from_instruction(&decoded, self.options.target)
+                .map_err(|e| e.to_string())
+                .and_then(|opcode| opcode.to_words().map_err(|e| e.to_string()))
+                .map_err(|e| format!("{:#06x}: '{}' does not encode: {}", offset, text, e))?;
+            let reencoded: Vec<u8> = reencoded.iter().flat_map(|w| w.to_be_bytes()).collect();
+            if reencoded != original {
+                return Err(format!(
+                    "{:#06x}: {} decodes to '{}' which encodes to {}",
+                    offset,
+                    hex(original),
+                    text,
+                    hex(&reencoded)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Source of the entry that emits the byte at `address`
+    pub fn span_at(&self, address: usize) -> Option<&SourceSpan> {
+        self.instructions
+            .iter()
+            .enumerate()
+            .find(|(index, (_, offset, _))| {
+                (*offset..offset + self.entry_size(*index)).contains(&address)
+            })
+            .map(|(_, (_, _, span))| span)
+    }
+
+    /// Sizes can depend on an entry's address and on label values, which in turn depend on sizes.
+    /// The layout is repeated until the label addresses reach a fixed point.
+    fn update_offsets(&mut self) {
+        for _ in 0..Assembly::MAX_LAYOUT_PASSES {
+            let mut address = self.options.offset;
+            for (i, off, _) in self.instructions.iter_mut() {
+                *off = address;
+                address += i.get_byte_size_at(address, &self.labels);
+            }
+
+            let mut label_map: HashMap<String, usize> = HashMap::new();
+            if let Some(name) = &self.options.offset_symbol {
+                label_map.insert(name.clone(), self.options.offset);
             }
+            for (i, off, _) in self.instructions.iter() {
+                if let Some(name) = i.label_name() {
+                    label_map.insert(name.to_string(), *off);
+                }
+            }
+            if label_map == self.labels {
+                break;
+            }
+            self.labels = label_map;
         }
+    }
 
-        for (i, _) in self.instructions.iter_mut() {
+    fn add_symbol(&mut self, name: &str, kind: SymbolKind, defined_at: &SourceSpan) {
+        self.symbols.insert(
+            name.to_string(),
+            Symbol {
+                kind,
+                defined_at: defined_at.clone(),
+                references: Vec::new(),
+            },
+        );
+    }
+
+    /// Checks typed defines hold what their kind promises. A later untyped define of the same
+    /// name keeps the kind, so a register alias cannot quietly become a number.
+    fn check_define_kinds(&self, diagnostics: &mut Diagnostics) {
+        let mut kinds: HashMap<&str, DefineKind> = HashMap::new();
+        for (entry, _, span) in self.instructions.iter() {
+            let AsmEnum::Define(d) = entry else { continue };
+            let kind = match (d.kind, kinds.get(d.key.as_str())) {
+                (Some(kind), _) => {
+                    kinds.insert(&d.key, kind);
+                    kind
+                }
+                (None, Some(kind)) => *kind,
+                (None, None) => continue,
+            };
+            let problem = match kind.max() {
+                None if !Operand::is_register_name(&d.value) => "is not a register".to_string(),
+                None => continue,
+                // Values that do not evaluate are reported where they are used
+                Some(max) => match evaluate(&d.value, &self.labels) {
+                    Ok(n) if !(0..=max).contains(&n) => format!("is not 0 to {}", max),
+                    _ => continue,
+                },
+            };
+            diagnostics.error_at(
+                span,
+                format!(
+                    "'{}' is defined as {}, but its value {} {}",
+                    d.key,
+                    kind.name(),
+                    d.value,
+                    problem
+                ),
+            );
+        }
+    }
+
+    /// Reports labels defined more than once. Labels resolve globally across every file, so a
+    /// second definition would silently move every reference to it.
+    fn check_duplicate_labels(&self, diagnostics: &mut Diagnostics) {
+        let mut seen: HashMap<&str, &SourceSpan> = HashMap::new();
+        for (i, _, span) in self.instructions.iter() {
+            if let Some(name) = i.label_name() {
+                if self.options.offset_symbol.as_deref() == Some(name) {
+                    diagnostics.report(AssemblyError::DuplicateSymbol {
+                        span: Some(span.clone()),
+                        name: name.to_string(),
+                        first: None,
+                    });
+                }
+                if let Some(first) = seen.insert(name, span) {
+                    diagnostics.report(AssemblyError::DuplicateSymbol {
+                        span: Some(span.clone()),
+                        name: name.to_string(),
+                        first: Some(first.clone()),
+                    });
+                    seen.insert(name, first);
+                }
+            }
+        }
+    }
+
+    /// Warns about every label and define that is never referenced. Labels at the load
+    /// address mark the entry point, which is reached without being referenced.
+    fn warn_unused(&self, diagnostics: &mut Diagnostics) {
+        for (name, symbol) in self.symbols.iter() {
+            let is_entry = symbol.kind == SymbolKind::Label
+                && (self.labels.get(name) == Some(&self.options.offset)
+                    || self.options.entry.as_ref() == Some(name));
+            if symbol.references.is_empty() && !is_entry {
+                diagnostics.warning_at(
+                    &symbol.defined_at,
+                    format!("Unused {} '{}'", symbol.kind, name),
+                );
+            }
+        }
+    }
+
+    /// Reports an `entry` that is not a label
+    fn check_entry(&self, diagnostics: &mut Diagnostics) {
+        let Some(entry) = &self.options.entry else {
+            return;
+        };
+        match self.symbols.get(entry) {
+            Some(symbol) if symbol.kind == SymbolKind::Label => {}
+            Some(symbol) => diagnostics.error_at(
+                &symbol.defined_at,
+                format!("Entry '{}' is a {}, not a label", entry, symbol.kind),
+            ),
+            None => diagnostics.error(format!("Entry label '{}' is not defined", entry)),
+        }
+    }
+
+    /// Bytes of data directly after the label at `index`, up to the next label or instruction
+    fn data_size_after(&self, index: usize) -> usize {
+        (index + 1..self.instructions.len())
+            .take_while(|&i| match &self.instructions[i].0 {
+                AsmEnum::Directive(_) => self.instructions[i].0.label_name().is_none(),
+                AsmEnum::Define(_) => true,
+                _ => false,
+            })
+            .map(|i| self.entry_size(i))
+            .sum()
+    }
+
+    /// Warns when a DRW draws more rows than the data the preceding `LD I, label` points at.
+    /// This is a heuristic: only a label loaded directly, with no label or change to I in
+    /// between, is followed.
+    fn warn_sprite_overread(&self, diagnostics: &mut Diagnostics) {
+        let data_labels: HashMap<&str, usize> = self
+            .instructions
+            .iter()
+            .enumerate()
+            .filter_map(|(index, (entry, ..))| Some((entry.label_name()?, index)))
+            .collect();
+        // Label that I holds and the bytes of data after it
+        let mut sprite: Option<(&str, usize)> = None;
+        for (i, _, span) in self.instructions.iter() {
+            let inst = match i {
+                AsmEnum::Instruction(inst) => inst,
+                _ if i.label_name().is_some() => {
+                    sprite = None;
+                    continue;
+                }
+                _ => continue,
+            };
+            let mnemonic = inst.mnemonic.to_uppercase();
+            let loads_i = inst
+                .args
+                .first()
+                .is_some_and(|a| a.repr.eq_ignore_ascii_case("I"));
+            if mnemonic == "LD" && loads_i && inst.args.len() == 2 {
+                sprite = inst.args[1]
+                    .symbol
+                    .as_deref()
+                    .and_then(|name| Some((name, self.data_size_after(*data_labels.get(name)?))));
+            } else if loads_i {
+                sprite = None;
+            } else if mnemonic == "DRW" {
+                let (Some((name, size)), Some(Ok(rows))) =
+                    (sprite, inst.args.get(2).map(|a| a.parse()))
+                else {
+                    continue;
+                };
+                // DRW Vx, Vy, 0 draws a 16x16 sprite of 32 bytes
+                let rows = if rows == 0 { 32 } else { rows as usize };
+                if rows > size {
+                    diagnostics.warning_at(
+                        span,
+                        format!(
+                            "DRW reads {} bytes from '{}', which only has {}",
+                            rows, name, size
+                        ),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Warns about a DRW that some path from the start of the program reaches without I being
+    /// set. This is a heuristic: paths are followed through jumps, skips and into subroutines,
+    /// but stop at the return from a CALL, which may set I, and at JP V0.
+    fn warn_draw_without_i(&self, diagnostics: &mut Diagnostics) {
+        let sets_i = |inst: &Instruction| {
+            let mnemonic = inst.mnemonic.to_uppercase();
+            let first = inst.args.first().map(|a| a.repr.to_uppercase());
+            matches!(first.as_deref(), Some("I"))
+                || matches!(mnemonic.as_str(), "FONT" | "HIFONT")
+                || (mnemonic == "LD" && matches!(first.as_deref(), Some("F" | "HF")))
+        };
+        let instruction = |index: usize| match &self.instructions[index].0 {
+            AsmEnum::Instruction(inst) => inst,
+            _ => unreachable!("blocks only hold instructions"),
+        };
+
+        let blocks = self.basic_blocks();
+        let starts: Vec<usize> = blocks.keys().copied().collect();
+        // Blocks entered with I unset, from the first one on
+        let mut unset: HashSet<usize> = HashSet::new();
+        let mut pending: Vec<usize> = starts.first().copied().into_iter().collect();
+        while let Some(start) = pending.pop() {
+            if !unset.insert(start) {
+                continue;
+            }
+            let indices = &blocks[&start];
+            if indices.iter().any(|&index| sets_i(instruction(index))) {
+                continue;
+            }
+            let last = *indices.last().unwrap();
+            let inst = instruction(last);
+            let after = self.instructions[last].1 + self.entry_size(last);
+            let next = starts.iter().copied().find(|&s| s >= after);
+            match inst.mnemonic.to_uppercase().as_str() {
+                "JP" if inst.args.len() == 1 => pending.extend(Self::jump_target(inst)),
+                "CALL" => pending.extend(Self::jump_target(inst)),
+                "JP" | "RET" | "EXIT" => {}
+                "SE" | "SNE" | "SKP" | "SKNP" => {
+                    pending.extend(next);
+                    // Skipping lands part way into a block when the skipped instruction does
+                    // not end one, which is left alone
+                    let skipped = next.and_then(|n| blocks[&n].first().copied());
+                    pending.extend(skipped.and_then(|index| {
+                        let after = self.instructions[index].1 + self.entry_size(index);
+                        blocks.contains_key(&after).then_some(after)
+                    }));
+                }
+                _ => pending.extend(next),
+            }
+        }
+
+        for start in starts.iter().filter(|start| unset.contains(start)) {
+            for &index in &blocks[start] {
+                let inst = instruction(index);
+                if sets_i(inst) {
+                    break;
+                }
+                if inst.mnemonic.eq_ignore_ascii_case("DRW") {
+                    diagnostics.warning_at(
+                        &self.instructions[index].2,
+                        "DRW can run before I is set, add an LD I before it".to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Warns when a value put in VF is overwritten by a flag-setting instruction before anything
+    /// reads it. Only straight-line code is followed: a label, data, jump or skip in between
+    /// forgets the value, since VF may be read somewhere else.
+    fn warn_clobbered_flag(&self, diagnostics: &mut Diagnostics) {
+        // Where the value still unread in VF was written
+        let mut written: Option<&SourceSpan> = None;
+        for (i, _, span) in self.instructions.iter() {
+            let AsmEnum::Instruction(inst) = i else {
+                written = None;
+                continue;
+            };
+            let (reads, writes) = inst.register_roles();
+            if reads.contains(&0xF) {
+                written = None;
+            }
+            if let (Some(at), true) = (written, inst.sets_flag()) {
+                diagnostics.warning_at(
+                    at,
+                    format!(
+                        "VF is overwritten by {} on line {} before it is read",
+                        inst.mnemonic.to_uppercase(),
+                        span.line
+                    ),
+                );
+            }
+            let mnemonic = inst.mnemonic.to_uppercase();
+            let branches = matches!(
+                mnemonic.as_str(),
+                "JP" | "CALL" | "RET" | "EXIT" | "SE" | "SNE" | "SKP" | "SKNP"
+            );
+            written = (writes.contains(&0xF) && !inst.sets_flag() && !branches).then_some(span);
+        }
+    }
+
+    /// Warns about SYS, which modern interpreters ignore. It is still encoded so old ROMs build.
+    fn warn_sys(&self, diagnostics: &mut Diagnostics) {
+        for (i, _, span) in self.instructions.iter() {
             if let AsmEnum::Instruction(inst) = i {
-                for arg in inst.args.iter_mut() {
-                    if label_map.contains_key(&arg.repr) {
-                        *arg = Operand::new(label_map[&arg.repr].to_string());
+                if inst.mnemonic.eq_ignore_ascii_case("SYS") {
+                    diagnostics.warning_at(
+                        span,
+                        "SYS is ignored by modern interpreters, use CALL or remove it".to_string(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Warns about code following an unconditional JP, RET or EXIT with no label in between, as
+    /// nothing can reach it. Data is expected there and not warned about.
+    fn warn_unreachable(&self, diagnostics: &mut Diagnostics) {
+        let mut unreachable = false;
+        let mut after_skip = false;
+        for (i, _, span) in self.instructions.iter() {
+            match i {
+                AsmEnum::Label(_) => unreachable = false,
+                AsmEnum::Instruction(inst) => {
+                    // Only the start of each run of dead code is warned about
+                    if unreachable {
+                        diagnostics.warning_at(span, format!("Unreachable {}", inst.mnemonic));
+                    }
+                    let mnemonic = inst.mnemonic.to_uppercase();
+                    // A skip before the jump makes it conditional
+                    unreachable = !after_skip && matches!(mnemonic.as_str(), "JP" | "RET" | "EXIT");
+                    after_skip = matches!(mnemonic.as_str(), "SE" | "SNE" | "SKP" | "SKNP");
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Reports a program that runs past the end of the target's memory
+    fn check_memory_size(&self, diagnostics: &mut Diagnostics) {
+        let size = self.options.target.memory_size();
+        let end = self
+            .instructions
+            .iter()
+            .enumerate()
+            .map(|(index, (_, offset, _))| offset + self.entry_size(index))
+            .max()
+            .unwrap_or(self.options.offset);
+        if end > size {
+            diagnostics.report(AssemblyError::range(format!(
+                "Program ends at {:#06x}, past the {} bytes of memory on --target {}",
+                end,
+                size,
+                self.options.target.name()
+            )));
+        }
+        let rom_size = end.saturating_sub(self.options.offset);
+        if let Some(max) = self.options.max_rom_size.filter(|max| rom_size > *max) {
+            diagnostics.report(AssemblyError::range(format!(
+                "Program is {} bytes, {} over the --max-rom-size of {}",
+                rom_size,
+                rom_size - max,
+                max
+            )));
+        }
+    }
+
+    /// Checks SHR/SHL against the configured shift quirk, see ShiftQuirk
+    fn check_shift_quirks(&self, diagnostics: &mut Diagnostics) {
+        let quirk = match self.options.shift_quirk {
+            Some(quirk) => quirk,
+            None => return,
+        };
+        for (i, _, span) in self.instructions.iter() {
+            let inst = match i {
+                AsmEnum::Instruction(inst) => inst,
+                _ => continue,
+            };
+            let mnemonic = inst.mnemonic.to_uppercase();
+            if mnemonic != "SHR" && mnemonic != "SHL" {
+                continue;
+            }
+            match (quirk, inst.args.len()) {
+                (ShiftQuirk::Legacy, 1) => diagnostics.error_at(
+                    span,
+                    format!(
+                        "{} needs Vy with the legacy shift quirk: {} Vx, Vy",
+                        mnemonic, mnemonic
+                    ),
+                ),
+                (ShiftQuirk::Modern, 2) => diagnostics.warning_at(
+                    span,
+                    format!(
+                        "{} ignores Vy with the modern shift quirk, use {} Vx",
+                        mnemonic, mnemonic
+                    ),
+                ),
+                _ => {}
+            }
+        }
+    }
+
+    fn add_reference(symbols: &mut BTreeMap<String, Symbol>, name: &str, span: &SourceSpan) {
+        if let Some(symbol) = symbols.get_mut(name) {
+            symbol.references.push(span.clone());
+        }
+    }
+
+    fn update_labels(&mut self) {
+        for i in 0..self.instructions.len() {
+            let (entry, _, span) = &self.instructions[i];
+            if let Some(name) = entry.label_name() {
+                let (name, span) = (name.to_string(), span.clone());
+                self.add_symbol(&name, SymbolKind::Label, &span);
+            }
+        }
+
+        let label_map = &self.labels;
+        for (i, _, span) in self.instructions.iter_mut() {
+            match i {
+                AsmEnum::Instruction(inst) => {
+                    for arg in inst.args.iter_mut() {
+                        if label_map.contains_key(&arg.repr) {
+                            Assembly::add_reference(&mut self.symbols, &arg.repr, span);
+                            arg.substitute(label_map[&arg.repr].to_string());
+                        } else if expression_terms(&arg.repr).len() > 1 {
+                            for (_, term) in expression_terms(&arg.repr) {
+                                Assembly::add_reference(&mut self.symbols, term, span);
+                            }
+                            // A bad expression is left as is and reported when the bytes are emitted
+                            if let Ok(n) = evaluate(&arg.repr, label_map) {
+                                arg.substitute(n.to_string());
+                            }
+                        }
+                    }
+                }
+                // Directives read labels while being laid out, they are only recorded here
+                AsmEnum::Directive(dir) if !matches!(dir.mnemonic.as_str(), "text" | "here") => {
+                    for arg in dir.args.iter() {
+                        for (_, term) in expression_terms(arg) {
+                            if label_map.contains_key(term) {
+                                Assembly::add_reference(&mut self.symbols, term, span);
+                            }
+                        }
                     }
                 }
+                _ => {}
             }
         }
     }
 
+    /// Substitutes defines into every operand. Every define is collected first, so a define can
+    /// be used on lines before the one it is defined on. Preprocessing such as `times` counts is
+    /// evaluated in order while reading and only sees defines from earlier lines.
     fn update_defines(&mut self) {
         let mut define_map: HashMap<String, String> = HashMap::new();
-        for (i, _) in self.instructions.iter() {
-            if let AsmEnum::Define(d) = i {
-                define_map.insert(d.key.clone(), d.value.clone());
+        for i in 0..self.instructions.len() {
+            if let (AsmEnum::Define(d), _, span) = &self.instructions[i] {
+                // Registers are stored uppercase so `define POS v3` reads the same as `V3`
+                let value = if Operand::is_register_name(&d.value) {
+                    d.value.to_uppercase()
+                } else {
+                    d.value.clone()
+                };
+                define_map.insert(d.key.clone(), value);
+                let (key, span) = (d.key.clone(), span.clone());
+                self.add_symbol(&key, SymbolKind::Define, &span);
             }
         }
 
-        for (i, _) in self.instructions.iter_mut() {
+        for (i, _, span) in self.instructions.iter_mut() {
             match i {
                 AsmEnum::Instruction(inst) => {
                     for arg in inst.args.iter_mut() {
                         if define_map.contains_key(&arg.repr) {
-                            *arg = Operand::new(define_map[&arg.repr].to_string());
+                            Assembly::add_reference(&mut self.symbols, &arg.repr, span);
+                            arg.substitute(define_map[&arg.repr].to_string());
+                        } else if expression_terms(&arg.repr).len() > 1 {
+                            let (expr, replaced) = substitute_terms(&arg.repr, &define_map);
+                            for name in replaced {
+                                Assembly::add_reference(&mut self.symbols, &name, span);
+                            }
+                            arg.substitute(expr);
                         }
                     }
                 }
-                AsmEnum::Directive(dir) => {
-                    for arg in dir.args.iter_mut() {
+                // The name given to `here` is being defined, not used
+                AsmEnum::Directive(dir) if dir.mnemonic != "here" => {
+                    let is_text = dir.mnemonic.eq_ignore_ascii_case("text");
+                    dir.sources = vec![None; dir.args.len()];
+                    for (arg, source) in dir.args.iter_mut().zip(dir.sources.iter_mut()) {
                         if define_map.contains_key(arg) {
-                            *arg = define_map[arg].clone();
+                            Assembly::add_reference(&mut self.symbols, arg, span);
+                            let value = define_map[arg].clone();
+                            *source = Some(std::mem::replace(arg, value));
+                        } else if !is_text && expression_terms(arg).len() > 1 {
+                            let (expr, replaced) = substitute_terms(arg, &define_map);
+                            for name in replaced {
+                                Assembly::add_reference(&mut self.symbols, &name, span);
+                            }
+                            *source = Some(std::mem::replace(arg, expr));
                         }
                     }
                 }
@@ -348,62 +1602,188 @@ impl Assembly {
         }
     }
 
-    pub fn to_bytes(&mut self) -> Vec<u8> {
-        self.update_labels();
-
-        let mut bytes: Vec<u8> = Vec::new();
-        for (i, _) in self.instructions.iter() {
+    pub fn to_bytes(&self, diagnostics: &mut Diagnostics) -> Vec<u8> {
+        let total: usize = self
+            .instructions
+            .iter()
+            .map(|(i, off, _)| i.get_byte_size_at(*off, &self.labels))
+            .sum();
+        let mut bytes: Vec<u8> = Vec::with_capacity(total);
+        for (i, off, span) in self.instructions.iter() {
             match i {
                 AsmEnum::Instruction(inst) => {
-                    let opcode = Opcode::from_instruction(inst.clone());
+                    let opcode = Opcode::from_instruction(inst, self.options.target);
 
                     match opcode {
-                        Some(opcode) => match opcode.to_bytes() {
-                            Ok(b) => {
-                                bytes.push((b >> 8) as u8);
-                                bytes.push((b & 0xFF) as u8);
+                        Ok(opcode) => match opcode.to_words() {
+                            Ok(words) => {
+                                for w in words {
+                                    bytes.push((w >> 8) as u8);
+                                    bytes.push((w & 0xFF) as u8);
+                                }
                             }
-                            Err(e) => panic!("Unable to convert to bytes: {}", e),
+                            Err(e) => diagnostics.report(self.conversion_error(span, e)),
                         },
-                        None => panic!("Error: Invalid instruction {:?}", inst),
+                        Err(e) => diagnostics.report(e.at(span)),
                     }
                 }
                 AsmEnum::Directive(dir) => match dir.mnemonic.to_lowercase().as_str() {
                     "db" => {
-                        for arg in dir.args.iter() {
-                            match Operand::parse_numeric_str(arg.clone()) {
-                                Ok(n) => bytes.push(n as u8),
-                                Err(e) => panic!("Unable to convert to bytes: {}", e),
+                        for (index, arg) in dir.args.iter().enumerate() {
+                            match evaluate(arg, &self.labels)
+                                .and_then(|n| Operand::byte_from(n, arg))
+                            {
+                                Ok(n) => bytes.push(n),
+                                Err(e) => diagnostics.report(
+                                    self.conversion_error(span, e.with_source(dir.source(index))),
+                                ),
                             }
                         }
                     }
+                    "dn" => {
+                        let mut nibbles = Vec::with_capacity(dir.args.len());
+                        for (index, arg) in dir.args.iter().enumerate() {
+                            match Directive::parse_arg(arg, &self.labels) {
+                                Ok(n) if n <= 0xF => nibbles.push(n as u8),
+                                Ok(_) => diagnostics.error_on(
+                                    span,
+                                    dir.source(index).unwrap_or(arg),
+                                    format!("Value does not fit in a nibble: {}", arg),
+                                ),
+                                Err(e) => diagnostics.report(
+                                    self.conversion_error(span, e.with_source(dir.source(index))),
+                                ),
+                            }
+                        }
+                        for pair in nibbles.chunks(2) {
+                            bytes.push(pair[0] << 4 | pair.get(1).copied().unwrap_or(0));
+                        }
+                    }
                     "dw" => {
-                        for arg in dir.args.iter() {
-                            match Operand::parse_numeric_str(arg.clone()) {
+                        for (index, arg) in dir.args.iter().enumerate() {
+                            match Directive::parse_arg(arg, &self.labels) {
                                 Ok(n) => {
                                     bytes.push((n >> 8) as u8);
                                     bytes.push((n & 0xFF) as u8);
                                 }
-                                Err(e) => panic!("Unable to convert to bytes: {}", e),
+                                Err(e) => diagnostics.report(
+                                    self.conversion_error(span, e.with_source(dir.source(index))),
+                                ),
                             }
                         }
                     }
                     "text" => {
                         for arg in dir.args.iter() {
+                            // Anything past ASCII, like a pasted smart quote, would not fit a byte
+                            if let Some(c) = arg.chars().find(|c| !c.is_ascii()) {
+                                diagnostics.error_at(
+                                    span,
+                                    format!(
+                                        "text can only hold ASCII, found '{}' (U+{:04X}) in \"{}\"",
+                                        c, c as u32, arg
+                                    ),
+                                );
+                            }
                             for c in arg.chars() {
                                 bytes.push(c as u8);
                             }
                             bytes.push(0);
                         }
                     }
-                    "offset" => match Operand::parse_numeric_str(dir.args[0].clone()) {
-                        Ok(n) => {
-                            for _ in 0..n {
-                                bytes.push(0);
+                    "font" => bytes.extend_from_slice(&FONT),
+                    "bigfont" => {
+                        if self.options.target < Target::SChip {
+                            diagnostics
+                                .error_at(span, "bigfont requires --target schip".to_string());
+                        }
+                        bytes.extend_from_slice(&BIG_FONT);
+                    }
+                    "sprite16" => {
+                        if self.options.target < Target::SChip {
+                            diagnostics
+                                .error_at(span, "sprite16 requires --target schip".to_string());
+                        }
+                        if dir.args.len() != 16 {
+                            diagnostics.error_at(
+                                span,
+                                format!("sprite16 needs 16 rows, found {}", dir.args.len()),
+                            );
+                        }
+                        for (index, arg) in dir
+                            .args
+                            .iter()
+                            .chain(std::iter::repeat(&"0".to_string()))
+                            .take(16)
+                            .enumerate()
+                        {
+                            match Operand::parse_numeric_str(arg) {
+                                Ok(row) => bytes.extend_from_slice(&row.to_be_bytes()),
+                                Err(e) => {
+                                    bytes.extend_from_slice(&[0, 0]);
+                                    let e = e.with_source(dir.source(index));
+                                    diagnostics.report(self.conversion_error(span, e))
+                                }
                             }
                         }
-                        Err(e) => panic!("Unable to convert to bytes: {}", e),
+                    }
+                    "audio" => {
+                        if self.options.target < Target::XoChip {
+                            diagnostics
+                                .error_at(span, "audio requires --target xochip".to_string());
+                        }
+                        if dir.args.len() != 16 {
+                            diagnostics.error_at(
+                                span,
+                                format!("audio needs 16 bytes, found {}", dir.args.len()),
+                            );
+                        }
+                        for (index, arg) in dir
+                            .args
+                            .iter()
+                            .chain(std::iter::repeat(&"0".to_string()))
+                            .take(16)
+                            .enumerate()
+                        {
+                            match evaluate(arg, &self.labels)
+                                .and_then(|n| Operand::byte_from(n, arg))
+                            {
+                                Ok(n) => bytes.push(n),
+                                Err(e) => {
+                                    bytes.push(0);
+                                    let e = e.with_source(dir.source(index));
+                                    diagnostics.report(self.conversion_error(span, e))
+                                }
+                            }
+                        }
+                    }
+                    "section" => match dir.section_base(&self.labels) {
+                        Ok(base) if base < *off => diagnostics.error_at(
+                            span,
+                            format!(
+                                "Section '{}' at {:#06x} starts before the end of what comes before it, {:#06x}",
+                                dir.first_arg(),
+                                base,
+                                off
+                            ),
+                        ),
+                        Ok(_) => {
+                            let gap = i.get_byte_size_at(*off, &self.labels);
+                            bytes.resize(bytes.len() + gap, self.options.fill)
+                        }
+                        Err(e) => diagnostics
+                            .report(self.conversion_error(span, e.with_source(dir.source(0)))),
                     },
+                    "offset" | "align" => {
+                        match Directive::parse_arg(dir.first_arg(), &self.labels) {
+                            Ok(_) => {
+                                let gap = i.get_byte_size_at(*off, &self.labels);
+                                bytes.resize(bytes.len() + gap, self.options.fill)
+                            }
+                            Err(e) => diagnostics.report(
+                                self.conversion_error(span, e.with_source(dir.source(0))),
+                            ),
+                        }
+                    }
                     _ => {}
                 },
                 _ => {}
@@ -411,41 +1791,214 @@ impl Assembly {
         }
         bytes
     }
+
+    /// The error for a value that could not be converted at `span`. When the failing operand
+    /// took its value from defines, a note such as ", value '0xZZ' (from define X at line 3)"
+    /// points back at them: only the defines whose own value is bad when there are any
+    fn conversion_error(&self, span: &SourceSpan, e: ParseOperandError) -> AssemblyError {
+        let defines: Vec<(&Define, &SourceSpan)> = e
+            .source
+            .as_deref()
+            .map(expression_terms)
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(|(_, term)| {
+                self.instructions
+                    .iter()
+                    .rev()
+                    .find_map(|(entry, _, at)| match entry {
+                        AsmEnum::Define(d) if d.key == term => Some((d, at)),
+                        _ => None,
+                    })
+            })
+            .collect();
+        let bad: Vec<_> = defines
+            .iter()
+            .copied()
+            .filter(|(d, _)| evaluate(&d.value, &self.labels).is_err())
+            .collect();
+        let blamed = if bad.is_empty() { defines } else { bad };
+
+        let mut note = String::new();
+        for (d, at) in blamed {
+            let location = if at.file == span.file {
+                format!("line {}", at.line)
+            } else {
+                at.to_string()
+            };
+            note.push_str(&format!(
+                ", value '{}' (from define {} at {})",
+                d.value, d.key, location
+            ));
+        }
+        AssemblyError::conversion(span, e).with_note(&note)
+    }
 }
 impl fmt::Display for Assembly {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for (inst, off) in self.instructions.iter() {
-            write!(f, "{:#06x} {}\n", off, inst)?;
+        for (inst, off, _) in self.instructions.iter() {
+            writeln!(f, "{:#06x} {}", off, inst)?;
         }
         Ok(())
     }
 }
 
-fn without_comments(line: String) -> String {
-    line.split(';').collect::<Vec<&str>>()[0].to_string()
+/// Splits a line into its code and the comment after `comment`, ignoring any `comment` inside
+/// a quoted string or character
+pub(crate) fn split_comment(line: &str, comment: char) -> (&str, Option<&str>) {
+    let mut quote: Option<char> = None;
+    for (i, c) in line.char_indices() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            (None, _) if c == comment => return (&line[..i], Some(&line[i + c.len_utf8()..])),
+            _ => {}
+        }
+    }
+    (line, None)
 }
 
-fn extract_label(line: String) -> Option<(String, Option<String>)> {
-    match line.find(':') {
-        Some(_) => {
-            let split: Vec<&str> = line.split(':').collect();
-            let label = ":".to_string() + split[0].trim();
-            let line = split[1].trim().to_string();
-            if split[0].chars().all(|c| c != '\"' && c != '\'') {
-                return if line.is_empty() {
-                    Some((label, None))
-                } else {
-                    Some((label, Some(line)))
-                };
+fn without_comments(line: String, comment: char) -> String {
+    split_comment(&line, comment).0.to_string()
+}
+
+/// Splits `name: rest` into the label and the rest of the line. Only the first colon outside
+/// quotes ends a label, so `db ':'` and `text "a:b"` are left whole, and a quote before the colon
+/// means the line is not a label at all.
+pub(crate) fn extract_label(line: String) -> Option<(String, Option<String>)> {
+    // Colons are found the way comments are, skipping over quoted text
+    let (name, rest) = split_comment(&line, ':');
+    let rest = rest?.trim();
+    if name.contains(['"', '\'']) {
+        return None;
+    }
+    let label = ":".to_string() + name.trim();
+    Some((label, (!rest.is_empty()).then(|| rest.to_string())))
+}
+
+/// Whether `name` is a mnemonic, directive, keyword or register, which labels and defines may not shadow
+pub(crate) fn is_reserved_name(name: &str) -> bool {
+    supported_instructions()
+        .iter()
+        .any(|i| i.mnemonic.eq_ignore_ascii_case(name))
+        || Directive::VALID_DIRECTIVES
+            .iter()
+            .any(|d| d.eq_ignore_ascii_case(name))
+        || ["define", "include", "times"]
+            .iter()
+            .any(|k| k.eq_ignore_ascii_case(name))
+        || Operand::is_register_name(name)
+}
+
+/// Splits `times N line` into the count and the line to repeat. The count can be an expression
+/// such as `N + 1`, and runs up to the first mnemonic or directive after it.
+pub(crate) fn split_times(line: &str) -> Option<(&str, &str)> {
+    let (keyword, rest) = split_mnemonic(line);
+    if !keyword.eq_ignore_ascii_case("times") {
+        return None;
+    }
+    let rest = rest.trim();
+    // Where each word of rest starts
+    let words: Vec<usize> = rest
+        .char_indices()
+        .filter(|&(i, c)| {
+            !c.is_whitespace() && (i == 0 || rest[..i].ends_with(char::is_whitespace))
+        })
+        .map(|(i, _)| i)
+        .collect();
+    // Without a known mnemonic the count is one word, and the rest is reported as it is parsed
+    let at = words
+        .iter()
+        .skip(1)
+        .copied()
+        .find(|&at| is_mnemonic(split_mnemonic(&rest[at..]).0))
+        .or(words.get(1).copied())?;
+    Some((rest[..at].trim_end(), &rest[at..]))
+}
+
+/// Whether `word` is an instruction or directive mnemonic
+fn is_mnemonic(word: &str) -> bool {
+    supported_instructions()
+        .iter()
+        .any(|i| i.mnemonic.eq_ignore_ascii_case(word))
+        || Directive::VALID_DIRECTIVES
+            .iter()
+            .any(|d| d.eq_ignore_ascii_case(word))
+}
+
+/// Parses a label found by extract_label, rejecting reserved names
+pub(crate) fn parse_label(label: String) -> Result<Label, String> {
+    let label = Label::from_line(label);
+    if is_reserved_name(&label.name) {
+        return Err(format!(
+            "'{}' is reserved and cannot be used as a label",
+            label.name
+        ));
+    }
+    Ok(label)
+}
+
+/// Parses one line, with its label and comment already removed, into an entry
+pub(crate) fn parse_entry(line: String) -> Result<AsmEnum, String> {
+    let first_word = line.split_whitespace().next().unwrap_or_default();
+    if first_word == "define" {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        match words.len() {
+            3 => {}
+            4 if DefineKind::from_name(words[1]).is_some() => {}
+            4 => {
+                return Err(format!(
+                    "Unknown define kind '{}', expected reg, nibble, byte, addr or word",
+                    words[1]
+                ))
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid define, expected 'define KEY VALUE' or 'define KIND KEY VALUE': {}",
+                    line
+                ))
             }
-            None
         }
-        None => None,
+        let define = Define::from_line(line);
+        if is_reserved_name(&define.key) {
+            return Err(format!(
+                "'{}' is reserved and cannot be used as a define",
+                define.key
+            ));
+        }
+        Ok(AsmEnum::Define(define))
+    } else if first_word == "here" {
+        let directive = Directive::from_line(line);
+        match directive.args.as_slice() {
+            [name] if is_reserved_name(name) => Err(format!(
+                "'{}' is reserved and cannot be used as a label",
+                name
+            )),
+            [_] => Ok(AsmEnum::Directive(directive)),
+            _ => Err("Invalid here, expected 'here NAME'".to_string()),
+        }
+    } else if first_word == "section" {
+        let directive = Directive::from_line(line);
+        match directive.args.as_slice() {
+            [name, ..] if is_reserved_name(name) => Err(format!(
+                "'{}' is reserved and cannot be used as a section name",
+                name
+            )),
+            [_, at, _] if at.eq_ignore_ascii_case("at") => Ok(AsmEnum::Directive(directive)),
+            _ => Err("Invalid section, expected 'section NAME at ADDR'".to_string()),
+        }
+    } else if first_word == "font" && line.split_whitespace().nth(1).is_some() {
+        // `font Vx` is the FONT pseudo-instruction, the font directive takes no arguments
+        Ok(AsmEnum::Instruction(Instruction::from_line(line)))
+    } else if Directive::VALID_DIRECTIVES.contains(&first_word) {
+        Ok(AsmEnum::Directive(Directive::from_line(line)))
+    } else {
+        Ok(AsmEnum::Instruction(Instruction::from_line(line)))
     }
 }
 
-fn format_line(mut line: String) -> Option<String> {
-    line = without_comments(line);
+pub(crate) fn format_line(mut line: String, comment: char) -> Option<String> {
+    line = without_comments(line, comment);
     line = line.trim().to_string();
 
     if line.is_empty() {
@@ -455,76 +2008,509 @@ fn format_line(mut line: String) -> Option<String> {
     }
 }
 
-pub fn generate_full_asm(file_path: &str, offset: usize) -> Assembly {
-    let mut full_asm: Vec<AsmEnum> = Vec::new();
+/// What read_sources found
+struct Sources {
+    entries: Vec<(AsmEnum, SourceSpan)>,
+    /// Every file that was read, including includes
+    files: Vec<String>,
+    /// Defines used by `times` counts, which are gone once the lines are repeated
+    references: Vec<(String, SourceSpan)>,
+}
+
+/// Path a source file was found at and its lines
+type OpenedFile = (String, Vec<String>);
+
+/// Opens a queued file, trying the path resolved against the including file first and then the
+/// path as written
+fn read_file(resolved_path: &str, file_path: &str) -> Result<OpenedFile, String> {
+    let (file, opened_path) = match File::open(resolved_path) {
+        Ok(f) => (f, resolved_path.to_string()),
+        Err(_) => match File::open(file_path) {
+            Ok(f) => (f, file_path.to_string()),
+            Err(_) => return Err(format!("File not found: {}", file_path)),
+        },
+    };
+    let lines = BufReader::new(file)
+        .lines()
+        .collect::<Result<Vec<String>, _>>()
+        .map_err(|e| format!("Unable to read {}: {}", file_path, e))?;
+    Ok((opened_path, lines))
+}
+
+/// Removes `.` and folds `..` into the component before it, without touching the filesystem, so
+/// `lib/gfx/../common.asm` and `./lib/common.asm` both read `lib/common.asm`
+fn clean_path(path: &Path) -> PathBuf {
+    let mut cleaned = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => match cleaned.components().next_back() {
+                Some(Component::Normal(_)) => {
+                    cleaned.pop();
+                }
+                // Nothing to fold into at the root
+                Some(Component::RootDir | Component::Prefix(_)) => {}
+                _ => cleaned.push(".."),
+            },
+            other => cleaned.push(other),
+        }
+    }
+    cleaned
+}
+
+/// What names the file `path` is, to include it once however it was reached. Files that exist
+/// are compared by their canonical path, which also sees through links.
+fn file_key(path: &str) -> String {
+    let path = Path::new(path);
+    std::fs::canonicalize(path)
+        .unwrap_or_else(|_| clean_path(path))
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// What parsing one file produced, kept by ParseCache to replay while the file is unchanged
+#[derive(Clone, Default)]
+struct FileFragment {
+    entries: Vec<(AsmEnum, SourceSpan)>,
+    /// Files it includes in the order they appear, as resolved and as written
+    includes: Vec<(String, String)>,
+    references: Vec<(String, SourceSpan)>,
+    invalid_counts: Vec<(String, SourceSpan)>,
+    diagnostics: Vec<Diagnostic>,
+    /// Numeric defines from earlier files that `times` counts looked up, with their values then
+    earlier_defines: Vec<(String, Option<usize>)>,
+}
+
+/// Parsed files kept between assemblies, so reassembling only parses the files whose content
+/// changed. Labels and addresses are still resolved afresh every time, since they are global.
+#[derive(Default)]
+pub struct ParseCache {
+    /// Hash of each file's content and what parsing it produced, by path
+    files: HashMap<String, (u64, FileFragment)>,
+    /// Files parsed rather than reused, counted across every assembly
+    pub parses: usize,
+}
+
+impl ParseCache {
+    /// Parses a file, or replays its last parse when neither its content nor the earlier
+    /// defines its `times` counts used have changed
+    fn parse(
+        &mut self,
+        opened_path: &str,
+        lines: Vec<String>,
+        options: &AssemblerOptions,
+        earlier_defines: &HashMap<String, usize>,
+    ) -> FileFragment {
+        let mut hasher = DefaultHasher::new();
+        (options.comment, &lines).hash(&mut hasher);
+        let hash = hasher.finish();
+        if let Some((cached_hash, fragment)) = self.files.get(opened_path) {
+            let same_defines = fragment
+                .earlier_defines
+                .iter()
+                .all(|(name, value)| earlier_defines.get(name) == value.as_ref());
+            if *cached_hash == hash && same_defines {
+                return fragment.clone();
+            }
+        }
+        self.parses += 1;
+        let fragment = parse_file(opened_path, lines, options, earlier_defines);
+        self.files
+            .insert(opened_path.to_string(), (hash, fragment.clone()));
+        fragment
+    }
+}
+
+/// Every define in `entries` whose value is a plain number
+fn numeric_defines(entries: &[(AsmEnum, SourceSpan)]) -> HashMap<String, usize> {
+    entries
+        .iter()
+        .filter_map(|(entry, _)| match entry {
+            AsmEnum::Define(d) => Operand::parse_numeric_str(&d.value)
+                .ok()
+                .map(|n| (d.key.clone(), n as usize)),
+            _ => None,
+        })
+        .collect()
+}
 
-    let relative_path =
-        file_path.split('/').collect::<Vec<&str>>()[..file_path.split('/').count() - 1].join("/");
-    let mut file_queue: Vec<String> = vec![file_path.to_string()];
-    let mut all_files: Vec<String> = Vec::new();
-    while file_queue.len() > 0 {
-        let file_path = file_queue.pop().unwrap();
-        // Try to open file, if it fails try to find it in the same directory as the original
-        let file = match File::open(&file_path) {
-            Ok(f) => f,
-            Err(_) => File::open(format!("{}/{}", relative_path, file_path))
-                .expect(format!("File not found: {}", file_path).as_str()),
+/// Parses the lines of one file into entries. `earlier_defines` are the numeric defines of the
+/// files before it, which `times` counts can use.
+fn parse_file(
+    opened_path: &str,
+    lines: Vec<String>,
+    options: &AssemblerOptions,
+    earlier_defines: &HashMap<String, usize>,
+) -> FileFragment {
+    let mut fragment = FileFragment::default();
+    let mut diagnostics = Diagnostics::new();
+    let directory = Path::new(opened_path).parent().unwrap_or(Path::new(""));
+
+    // Each line as written, which spans keep after labels are split off and lines are joined
+    let texts: Vec<Arc<str>> = lines.iter().map(|l| Arc::from(l.as_str())).collect();
+    let mut line_queue = lines
+        .into_iter()
+        .enumerate()
+        .map(|(i, l)| (i + 1, l))
+        .collect::<Vec<(usize, String)>>()
+        .into_iter();
+    while let Some((line_number, line)) = line_queue.next() {
+        let mut line = match format_line(line, options.comment) {
+            Some(line) => line,
+            None => continue,
+        };
+        let span = SourceSpan {
+            file: opened_path.to_string(),
+            line: line_number,
+            text: texts[line_number - 1].clone(),
         };
 
-        let mut line_queue = BufReader::new(file)
-            .lines()
-            .map(|l| l.unwrap())
-            .collect::<Vec<String>>()
-            .into_iter();
-        while let Some(line) = line_queue.next() {
-            let mut line = match format_line(line) {
-                Some(line) => line,
-                None => continue,
-            };
+        // Parse included files
+        let split: Vec<&str> = line.split("include ").collect();
+        if split.len() > 1 {
+            split[1].replace("\"", "").split_whitespace().for_each(|s| {
+                let resolved = clean_path(&directory.join(s))
+                    .to_string_lossy()
+                    .into_owned();
+                fragment.includes.push((resolved, s.to_string()));
+            });
+            continue;
+        }
+
+        // Remove labels and put remaining in line_queue
+        if let Some((label, rem_line)) = extract_label(line.clone()) {
+            match parse_label(label) {
+                Ok(label) => fragment.entries.push((AsmEnum::Label(label), span)),
+                Err(e) => diagnostics.error_at(&span, e),
+            }
+            if let Some(rem_line) = rem_line {
+                // Put rem_line at the front of the line_queue
+                let as_iter = vec![(line_number, rem_line)].into_iter();
+                line_queue = as_iter
+                    .chain(line_queue)
+                    .collect::<Vec<(usize, String)>>()
+                    .into_iter();
+            }
+            continue;
+        }
 
-            // Parse included files
-            let split: Vec<&str> = line.split("include ").collect();
-            if split.len() > 1 {
-                split[1].replace("\"", "").split_whitespace().for_each(|s| {
-                    if !all_files.contains(&s.to_string()) {
-                        all_files.push(s.to_string());
-                        file_queue.push(s.to_string());
+        while line.ends_with(',')
+            || matches!(line.to_lowercase().as_str(), "db" | "sprite16" | "audio")
+        {
+            // A blank line, label or include ends the list instead of being swallowed by it
+            let next_line = line_queue
+                .as_slice()
+                .first()
+                .and_then(|(_, l)| format_line(l.clone(), options.comment))
+                .filter(|l| extract_label(l.clone()).is_none() && !l.contains("include "));
+            match next_line {
+                Some(next_line) => {
+                    line_queue.next();
+                    line = line + " " + next_line.as_str();
+                }
+                None => {
+                    if let Some(trimmed) = line.strip_suffix(',') {
+                        diagnostics.warning_at(
+                            &span,
+                            "Trailing comma is not followed by more values".to_string(),
+                        );
+                        line = trimmed.to_string();
                     }
-                });
-                continue;
+                    break;
+                }
             }
+        }
 
-            // Remove labels and put remaining in line_queue
-            if let Some((label, rem_line)) = extract_label(line.clone()) {
-                full_asm.push(AsmEnum::Label(Label::from_line(label)));
-                if let Some(rem_line) = rem_line {
-                    // Put rem_line at the front of the line_queue
-                    let as_iter = vec![rem_line].into_iter();
-                    line_queue = as_iter
-                        .chain(line_queue)
-                        .collect::<Vec<String>>()
-                        .into_iter();
+        // `times N line` repeats one line, N can use numeric defines from earlier lines
+        let times = split_times(&line);
+        if times.is_none() && split_mnemonic(&line).0.eq_ignore_ascii_case("times") {
+            let message = match split_mnemonic(&line).1.trim() {
+                "" => "times needs a count and a line to repeat".to_string(),
+                count => format!("times {} needs a line to repeat", count),
+            };
+            diagnostics.error_at(&span, message);
+            continue;
+        }
+        let (line, count) = match times {
+            Some((count, repeated)) => {
+                let mut defines = earlier_defines.clone();
+                defines.extend(numeric_defines(&fragment.entries));
+                for term in count_names(count) {
+                    fragment
+                        .earlier_defines
+                        .push((term.to_string(), earlier_defines.get(term).copied()));
+                    if defines.contains_key(term) {
+                        fragment.references.push((term.to_string(), span.clone()));
+                    }
+                }
+                let mnemonic = split_mnemonic(repeated).0;
+                if !is_mnemonic(mnemonic) {
+                    // Reported once rather than for every copy
+                    diagnostics.report(AssemblyError::UnknownMnemonic {
+                        span: Some(span.clone()),
+                        mnemonic: mnemonic.to_string(),
+                    });
+                    continue;
+                }
+                let memory = options.target.memory_size();
+                match evaluate_count(count, &defines) {
+                    // Even a byte a copy could not fit, and would take long to find out
+                    Some(n) if n > memory as i64 => {
+                        diagnostics.error_on(
+                            &span,
+                            count,
+                            format!(
+                                "times count {} is more than the {} bytes of memory on --target {}",
+                                n,
+                                memory,
+                                options.target.name()
+                            ),
+                        );
+                        continue;
+                    }
+                    Some(n) if n >= 0 => (repeated.to_string(), n as usize),
+                    // Reported once every define is known, to tell a late define from a typo
+                    _ => {
+                        fragment.invalid_counts.push((count.to_string(), span));
+                        continue;
+                    }
                 }
-                continue;
             }
+            None => (line, 1),
+        };
 
-            while line.ends_with(',') || line.to_lowercase() == "db" {
-                match format_line(line_queue.next().unwrap()) {
-                    Some(next_line) => line = line + " " + next_line.as_str(),
-                    None => break,
+        for _ in 0..count {
+            match parse_entry(line.clone()) {
+                Ok(entry) => fragment.entries.push((entry, span.clone())),
+                Err(e) => {
+                    diagnostics.error_at(&span, e);
+                    break;
                 }
             }
+        }
+    }
 
-            let first_word = line.split_whitespace().next().unwrap();
-            full_asm.push(if first_word == "define" {
-                AsmEnum::Define(Define::from_line(line))
-            } else if Directive::VALID_DIRECTIVES.contains(&first_word) {
-                AsmEnum::Directive(Directive::from_line(line))
-            } else {
-                AsmEnum::Instruction(Instruction::from_line(line))
-            });
+    fragment.diagnostics = diagnostics.items;
+    fragment
+}
+
+/// Reads `file_paths` and their includes into entries in program order, joining continued
+/// lines and splitting labels onto their own entries. `open` reads each file, given the path
+/// resolved against the including file and the path as written, as `read_file` does.
+fn read_sources(
+    file_paths: &[String],
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+    open: &mut dyn FnMut(&str, &str) -> Result<OpenedFile, String>,
+    mut cache: Option<&mut ParseCache>,
+) -> Sources {
+    let mut full_asm: Vec<(AsmEnum, SourceSpan)> = Vec::new();
+    let mut references: Vec<(String, SourceSpan)> = Vec::new();
+    let mut invalid_counts: Vec<(String, SourceSpan)> = Vec::new();
+
+    // Each file is queued with the path it resolves to and the path it was given as. The queue
+    // is popped from the back, so push the files in reverse to keep their order
+    let mut file_queue: Vec<(String, String)> = file_paths
+        .iter()
+        .rev()
+        .map(|path| (path.clone(), path.clone()))
+        .collect();
+    // Set of every file queued so far for cheap duplicate checks, the order is kept in opened_files
+    let mut all_files: HashSet<String> = file_paths.iter().map(|p| file_key(p)).collect();
+    let mut opened_files: Vec<String> = Vec::new();
+    while let Some((resolved_path, file_path)) = file_queue.pop() {
+        // Includes are relative to the including file, falling back to the working directory
+        let (opened_path, lines) = match open(&resolved_path, &file_path) {
+            Ok(opened) => opened,
+            Err(message) => {
+                diagnostics.report(AssemblyError::Io {
+                    path: file_path,
+                    message,
+                });
+                continue;
+            }
+        };
+        opened_files.push(opened_path.clone());
+
+        let earlier_defines = numeric_defines(&full_asm);
+        let fragment = match cache.as_deref_mut() {
+            Some(cache) => cache.parse(&opened_path, lines, options, &earlier_defines),
+            None => parse_file(&opened_path, lines, options, &earlier_defines),
+        };
+        full_asm.extend(fragment.entries);
+        references.extend(fragment.references);
+        invalid_counts.extend(fragment.invalid_counts);
+        diagnostics.items.extend(fragment.diagnostics);
+        for (resolved, written) in fragment.includes {
+            if all_files.insert(file_key(&resolved)) {
+                file_queue.push((resolved, written));
+            }
+        }
+    }
+
+    for (count, span) in invalid_counts {
+        let defined_later = count_names(&count).find_map(|term| {
+            full_asm.iter().find_map(|(entry, defined_at)| match entry {
+                AsmEnum::Define(d) if d.key == term => Some((term, defined_at)),
+                _ => None,
+            })
+        });
+        match defined_later {
+            Some((name, defined_at)) => diagnostics.error_on(
+                &span,
+                name,
+                format!(
+                    "'{}' is used in a times count before it is defined at {}",
+                    name, defined_at
+                ),
+            ),
+            None => diagnostics.error_on(&span, &count, format!("Invalid times count: {}", count)),
         }
     }
 
-    Assembly::new(full_asm, offset)
+    Sources {
+        entries: full_asm,
+        files: opened_files,
+        references,
+    }
+}
+
+/// Runs only the preprocessing done by read_sources and prints the flattened source, each
+/// line annotated with where it came from
+pub fn expand(
+    file_paths: &[String],
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+) -> String {
+    let entries = read_sources(file_paths, options, diagnostics, &mut read_file, None).entries;
+    // Annotations line up unless the code is longer than this
+    const WIDTH: usize = 32;
+    entries
+        .iter()
+        .map(|(entry, span)| {
+            format!(
+                "{:<WIDTH$} {} {}\n",
+                entry.to_source(),
+                options.comment,
+                span
+            )
+        })
+        .collect()
+}
+
+/// Prints the entries read_sources parsed `file_paths` into, one to a line with where it came
+/// from, before anything is resolved. Shows how each line was classified when debugging the
+/// parser.
+pub fn print_tree(
+    file_paths: &[String],
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+) -> String {
+    let entries = read_sources(file_paths, options, diagnostics, &mut read_file, None).entries;
+    entries
+        .iter()
+        .map(|(entry, span)| format!("{}: {}\n", span, entry))
+        .collect()
+}
+
+/// Assembles `file_paths` in the given order into one contiguous program,
+/// with each file's includes following it
+pub fn generate_full_asm(
+    file_paths: &[String],
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+) -> Assembly {
+    let sources = read_sources(file_paths, options, diagnostics, &mut read_file, None);
+    assemble_sources(sources, options, diagnostics)
+}
+
+/// Like `generate_full_asm`, but reuses what `cache` kept of files that have not changed since
+/// the last call, and keeps what it parses for the next one
+pub fn generate_full_asm_cached(
+    file_paths: &[String],
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+    cache: &mut ParseCache,
+) -> Assembly {
+    let sources = read_sources(
+        file_paths,
+        options,
+        diagnostics,
+        &mut read_file,
+        Some(cache),
+    );
+    assemble_sources(sources, options, diagnostics)
+}
+
+/// Assembles source lines held in memory as if they were the file `name`, for driving the
+/// assembler without touching the filesystem. Includes cannot be read this way, so each one
+/// is an error.
+pub fn assemble_lines(
+    lines: impl IntoIterator<Item = String>,
+    name: &str,
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+) -> Assembly {
+    let mut lines = Some(lines.into_iter().collect::<Vec<String>>());
+    let mut open = |_: &str, file_path: &str| match lines.take() {
+        Some(lines) => Ok((name.to_string(), lines)),
+        None => Err(format!(
+            "Cannot include {} when assembling lines from memory",
+            file_path
+        )),
+    };
+    let sources = read_sources(&[name.to_string()], options, diagnostics, &mut open, None);
+    assemble_sources(sources, options, diagnostics)
+}
+
+/// A `JP` to the entry label, for the start of the program when anything that emits bytes comes
+/// before the label. None when the label comes first or is missing.
+fn entry_jump(entries: &[(AsmEnum, SourceSpan)], entry: &str) -> Option<(AsmEnum, SourceSpan)> {
+    let (index, (_, span)) = entries
+        .iter()
+        .enumerate()
+        .find(|(_, (e, _))| matches!(e, AsmEnum::Label(l) if l.name == entry))?;
+    let code_before = entries[..index].iter().any(|(e, _)| {
+        matches!(e, AsmEnum::Instruction(_) | AsmEnum::Directive(_)) && e.label_name().is_none()
+    });
+    code_before.then(|| {
+        let jump = Instruction::from_line(format!("JP {}", entry));
+        (AsmEnum::Instruction(jump), span.clone())
+    })
+}
+
+/// Lays out and checks what read_sources found
+fn assemble_sources(
+    sources: Sources,
+    options: &AssemblerOptions,
+    diagnostics: &mut Diagnostics,
+) -> Assembly {
+    let mut entries = sources.entries;
+    if let Some(entry) = options.entry.as_deref().filter(|_| options.entry_jump) {
+        if let Some(jump) = entry_jump(&entries, entry) {
+            entries.insert(0, jump);
+        }
+    }
+    let mut assembly = Assembly::new(entries, sources.files, options.clone());
+    for (name, span) in sources.references {
+        Assembly::add_reference(&mut assembly.symbols, &name, &span);
+    }
+    assembly.check_duplicate_labels(diagnostics);
+    assembly.check_entry(diagnostics);
+    assembly.check_define_kinds(diagnostics);
+    assembly.warn_unused(diagnostics);
+    assembly.check_memory_size(diagnostics);
+    assembly.check_shift_quirks(diagnostics);
+    assembly.warn_sys(diagnostics);
+    if options.check_sprites {
+        assembly.warn_sprite_overread(diagnostics);
+    }
+    if options.check_draws {
+        assembly.warn_draw_without_i(diagnostics);
+    }
+    if options.check_flags {
+        assembly.warn_clobbered_flag(diagnostics);
+    }
+    assembly.warn_unreachable(diagnostics);
+    assembly
 }
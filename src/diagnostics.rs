@@ -0,0 +1,264 @@
+use crate::asm::{split_comment, SourceSpan};
+use crate::error::AssemblyError;
+use std::fmt;
+
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Severity {
+    Warning,
+    Error,
+}
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub span: Option<SourceSpan>,
+    /// What went wrong, for every error, None for warnings
+    pub error: Option<AssemblyError>,
+}
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(span) = &self.span {
+            write!(f, "{}: ", span)?;
+        }
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Where `token` starts in `line` as a whole word, ignoring case since mnemonics and registers
+/// are read either way
+fn find_token(line: &str, token: &str) -> Option<usize> {
+    if token.is_empty() {
+        return None;
+    }
+    let is_word = |c: char| c.is_ascii_alphanumeric() || c == '_';
+    let lower = line.to_ascii_lowercase();
+    let token = token.to_ascii_lowercase();
+    lower.match_indices(&token).map(|(at, _)| at).find(|&at| {
+        let before = lower[..at].chars().next_back();
+        let after = lower[at + token.len()..].chars().next();
+        // Only a token that starts or ends in a word character can run into its neighbours
+        let starts = !token.starts_with(is_word) || !before.is_some_and(is_word);
+        let ends = !token.ends_with(is_word) || !after.is_some_and(is_word);
+        starts && ends
+    })
+}
+
+/// Quotes and escapes a string for JSON
+fn json_string(value: &str) -> String {
+    let mut out = String::from("\"");
+    for c in value.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\t' => out.push_str("\\t"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+    out
+}
+
+impl Diagnostic {
+    /// The part of the line the diagnostic is about, None for warnings and errors about a
+    /// whole line
+    pub fn token(&self) -> Option<&str> {
+        self.error.as_ref().and_then(|e| e.token())
+    }
+
+    /// The column, from 1, of the token the diagnostic is about, or where the code on the line
+    /// starts when it has none. None without a span.
+    pub fn column(&self) -> Option<usize> {
+        let text = &self.span.as_ref()?.text;
+        let start = self
+            .token()
+            .and_then(|token| find_token(text, token))
+            .unwrap_or(text.len() - text.trim_start().len());
+        Some(text[..start].chars().count() + 1)
+    }
+
+    /// One JSON object with the severity, message, file, line and column, where the column is
+    /// that of `column`. Location fields are null without a span.
+    pub fn to_json(&self) -> String {
+        let location = match (&self.span, self.column()) {
+            (Some(span), Some(column)) => {
+                format!(
+                    "\"file\":{},\"line\":{},\"column\":{}",
+                    json_string(&span.file),
+                    span.line,
+                    column
+                )
+            }
+            _ => "\"file\":null,\"line\":null,\"column\":null".to_string(),
+        };
+        format!(
+            "{{\"severity\":{},\"message\":{},{}}}",
+            json_string(&self.severity.to_string()),
+            json_string(&self.message),
+            location
+        )
+    }
+
+    /// Renders the message followed by the source line it points at, with a caret underline
+    /// below its token, or below all of its code up to where a `comment` begins when it has
+    /// none. ANSI colors are used when `color` is set.
+    pub fn render(&self, color: bool, comment: char) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+        let severity = match self.severity {
+            Severity::Warning => paint(YELLOW, "warning"),
+            Severity::Error => paint(RED, "error"),
+        };
+        let mut out = match &self.span {
+            Some(span) => format!("{}: {}: {}\n", span, severity, paint(BOLD, &self.message)),
+            None => format!("{}: {}\n", severity, paint(BOLD, &self.message)),
+        };
+
+        let source_line = self
+            .span
+            .as_ref()
+            // Tabs would throw the underline out of line with the code
+            .map(|span| (span.line, span.text.replace('\t', "    ")));
+        if let Some((line_number, line)) = source_line {
+            let code = split_comment(&line, comment).0.trim_end();
+            let (start, width) = match self.token().and_then(|t| Some((find_token(code, t)?, t))) {
+                Some((start, token)) => (start, token.chars().count()),
+                None => {
+                    let indent = code.len() - code.trim_start().len();
+                    (indent, code[indent..].chars().count())
+                }
+            };
+            let underline = format!(
+                "{}{}",
+                " ".repeat(code[..start].chars().count()),
+                "^".repeat(width.max(1))
+            );
+            let gutter = " ".repeat(line_number.to_string().len());
+            let bar = paint(BLUE, "|");
+            out.push_str(&format!("{} {}\n", gutter, bar));
+            out.push_str(&format!(
+                "{} {} {}\n",
+                paint(BLUE, &line_number.to_string()),
+                bar,
+                line
+            ));
+            let underline = match self.severity {
+                Severity::Warning => paint(YELLOW, &underline),
+                Severity::Error => paint(RED, &underline),
+            };
+            out.push_str(&format!("{} {} {}\n", gutter, bar, underline));
+        }
+        out
+    }
+}
+
+/// Collects every problem found while assembling so they can all be
+/// reported at once instead of stopping at the first one.
+#[derive(Default)]
+pub struct Diagnostics {
+    pub items: Vec<Diagnostic>,
+}
+impl Diagnostics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn error(&mut self, message: String) {
+        self.report(AssemblyError::Parse {
+            span: None,
+            message,
+            token: None,
+        });
+    }
+
+    pub fn error_at(&mut self, span: &SourceSpan, message: String) {
+        self.report(AssemblyError::Parse {
+            span: Some(span.clone()),
+            message,
+            token: None,
+        });
+    }
+
+    /// Like `error_at`, blaming `token` on the line
+    pub fn error_on(&mut self, span: &SourceSpan, token: &str, message: String) {
+        self.report(AssemblyError::Parse {
+            span: Some(span.clone()),
+            message,
+            token: Some(token.to_string()),
+        });
+    }
+
+    /// Adds an error of a particular kind, at its span if it has one
+    pub fn report(&mut self, error: AssemblyError) {
+        self.items.push(Diagnostic {
+            severity: Severity::Error,
+            message: error.to_string(),
+            span: error.span().cloned(),
+            error: Some(error),
+        });
+    }
+
+    pub fn warning_at(&mut self, span: &SourceSpan, message: String) {
+        self.items.push(Diagnostic {
+            severity: Severity::Warning,
+            message,
+            span: Some(span.clone()),
+            error: None,
+        });
+    }
+
+    /// Every error reported, in order
+    pub fn errors(&self) -> impl Iterator<Item = &AssemblyError> {
+        self.items.iter().filter_map(|d| d.error.as_ref())
+    }
+
+    /// Every diagnostic as a JSON array, for editors and other tools
+    pub fn to_json(&self) -> String {
+        let items: Vec<String> = self.items.iter().map(|d| d.to_json()).collect();
+        format!("[{}]", items.join(","))
+    }
+
+    /// Turns every warning into an error, so a build with any fails
+    pub fn promote_warnings(&mut self) {
+        for diagnostic in self.items.iter_mut() {
+            if diagnostic.error.is_none() {
+                diagnostic.severity = Severity::Error;
+                diagnostic.error = Some(AssemblyError::Parse {
+                    span: diagnostic.span.clone(),
+                    message: diagnostic.message.clone(),
+                    token: None,
+                });
+            }
+        }
+    }
+
+    /// Forgets every warning, keeping the errors
+    pub fn drop_warnings(&mut self) {
+        self.items.retain(|d| d.severity == Severity::Error);
+    }
+
+    pub fn has_errors(&self) -> bool {
+        self.items.iter().any(|d| d.severity == Severity::Error)
+    }
+}
@@ -0,0 +1,77 @@
+use crate::asm::Operand;
+use crate::instructions::decode_with_names;
+use crate::options::Target;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Reads a CSV symbol map as written by `--symbol-map` into the names at each address
+pub fn parse_symbol_map(text: &str) -> Result<BTreeMap<usize, Vec<String>>, String> {
+    let mut symbols: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || (i == 0 && line.starts_with("address,name")) {
+            continue;
+        }
+        let (address, name) = line
+            .split_once(',')
+            .ok_or_else(|| format!("Symbol map line {}: expected address,name", i + 1))?;
+        let address = Operand::parse_numeric_str(address.trim())
+            .map_err(|_| format!("Symbol map line {}: invalid address {}", i + 1, address))?;
+        // Anything after the name, such as the flags, is not needed here
+        let name = name.split(',').next().unwrap_or_default();
+        symbols
+            .entry(address as usize)
+            .or_default()
+            .push(name.trim().to_string());
+    }
+    Ok(symbols)
+}
+
+/// Disassembles a ROM loaded at `offset` into source that assembles back to the same bytes.
+/// Every address in `symbols` gets its labels, and jump, call and `LD I` targets are written
+/// with the first name at their address. Words that do not decode, or that a label points
+/// into the middle of, are written as `db`.
+pub fn disassemble(
+    bytes: &[u8],
+    offset: usize,
+    symbols: &BTreeMap<usize, Vec<String>>,
+    target: Target,
+) -> String {
+    let mut out = String::new();
+    let labelled = |address: usize| symbols.contains_key(&address);
+    let mut i = 0;
+    while i < bytes.len() {
+        let address = offset + i;
+        for name in symbols.get(&address).into_iter().flatten() {
+            writeln!(out, "{}:", name).unwrap();
+        }
+        let decoded = decode_with_names(&bytes[i..], target, |address| {
+            symbols.get(&address).map(|names| names[0].clone())
+        })
+        .filter(|(_, size)| (1..*size).all(|k| !labelled(address + k)));
+        match decoded {
+            Some((text, size)) => {
+                writeln!(out, "    {}", text).unwrap();
+                i += size;
+            }
+            None => {
+                let size = if i + 1 < bytes.len() && !labelled(address + 1) {
+                    2
+                } else {
+                    1
+                };
+                let values: Vec<String> = bytes[i..i + size]
+                    .iter()
+                    .map(|b| format!("{:#04x}", b))
+                    .collect();
+                writeln!(out, "    db {}", values.join(", ")).unwrap();
+                i += size;
+            }
+        }
+    }
+    // Labels just past the end, such as one marking where the program ends
+    for name in symbols.get(&(offset + bytes.len())).into_iter().flatten() {
+        writeln!(out, "{}:", name).unwrap();
+    }
+    out
+}
@@ -0,0 +1,176 @@
+use crate::asm::{ParseOperandError, SourceSpan};
+use std::error::Error;
+use std::fmt;
+
+/// Every kind of failure assembling can report, with what it is about, so library users can
+/// match on them. Each error diagnostic carries one, and its message is the Display text.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AssemblyError {
+    /// A source file could not be read
+    Io { path: String, message: String },
+    /// A line that could not be understood, and anything not covered by another kind
+    Parse {
+        span: Option<SourceSpan>,
+        message: String,
+        /// The part of the line at fault, when it is known
+        token: Option<String>,
+    },
+    /// A name used as an operand that no label or define has
+    UndefinedSymbol {
+        span: Option<SourceSpan>,
+        name: String,
+    },
+    /// A label defined a second time, `first` is None when `--offset-symbol` took the name
+    DuplicateSymbol {
+        span: Option<SourceSpan>,
+        name: String,
+        first: Option<SourceSpan>,
+    },
+    /// A value or program too large for where it goes
+    RangeExceeded {
+        span: Option<SourceSpan>,
+        message: String,
+        /// The part of the line at fault, when it is known
+        token: Option<String>,
+    },
+    /// A mnemonic that is not an instruction
+    UnknownMnemonic {
+        span: Option<SourceSpan>,
+        mnemonic: String,
+    },
+    /// An instruction whose operands fit none of its forms, or that the target lacks
+    Encoding {
+        span: Option<SourceSpan>,
+        message: String,
+        /// The part of the line at fault, when it is known
+        token: Option<String>,
+    },
+}
+impl AssemblyError {
+    pub(crate) fn encoding(message: String) -> Self {
+        AssemblyError::Encoding {
+            span: None,
+            message,
+            token: None,
+        }
+    }
+
+    pub(crate) fn range(message: String) -> Self {
+        AssemblyError::RangeExceeded {
+            span: None,
+            message,
+            token: None,
+        }
+    }
+
+    /// An operand that failed to convert while emitting bytes
+    pub(crate) fn conversion(span: &SourceSpan, e: ParseOperandError) -> Self {
+        let message = format!("Unable to convert to bytes: {}", e);
+        let error = match e.undefined {
+            Some(name) => AssemblyError::UndefinedSymbol { span: None, name },
+            None if e.out_of_range => AssemblyError::range(message),
+            None => AssemblyError::Parse {
+                span: None,
+                message,
+                token: None,
+            },
+        };
+        match e.source {
+            Some(source) => error.with_token(&source).at(span),
+            None => error.at(span),
+        }
+    }
+
+    /// The line the error is on, None for errors about the whole program or a file
+    pub fn span(&self) -> Option<&SourceSpan> {
+        match self {
+            AssemblyError::Io { .. } => None,
+            AssemblyError::Parse { span, .. }
+            | AssemblyError::UndefinedSymbol { span, .. }
+            | AssemblyError::DuplicateSymbol { span, .. }
+            | AssemblyError::RangeExceeded { span, .. }
+            | AssemblyError::UnknownMnemonic { span, .. }
+            | AssemblyError::Encoding { span, .. } => span.as_ref(),
+        }
+    }
+
+    /// The part of the line the error is about, such as the operand that does not fit
+    pub fn token(&self) -> Option<&str> {
+        match self {
+            AssemblyError::Io { .. } => None,
+            AssemblyError::Parse { token, .. }
+            | AssemblyError::RangeExceeded { token, .. }
+            | AssemblyError::Encoding { token, .. } => token.as_deref(),
+            AssemblyError::UndefinedSymbol { name, .. }
+            | AssemblyError::DuplicateSymbol { name, .. } => Some(name),
+            AssemblyError::UnknownMnemonic { mnemonic, .. } => Some(mnemonic),
+        }
+    }
+
+    /// Blames `at_fault`, for the kinds that do not name what they are about
+    pub(crate) fn with_token(mut self, at_fault: &str) -> Self {
+        match &mut self {
+            AssemblyError::Parse { token, .. }
+            | AssemblyError::RangeExceeded { token, .. }
+            | AssemblyError::Encoding { token, .. } => *token = Some(at_fault.to_string()),
+            AssemblyError::Io { .. }
+            | AssemblyError::UndefinedSymbol { .. }
+            | AssemblyError::DuplicateSymbol { .. }
+            | AssemblyError::UnknownMnemonic { .. } => {}
+        }
+        self
+    }
+
+    /// Points the error at `span`
+    pub(crate) fn at(mut self, at: &SourceSpan) -> Self {
+        match &mut self {
+            AssemblyError::Io { .. } => {}
+            AssemblyError::Parse { span, .. }
+            | AssemblyError::UndefinedSymbol { span, .. }
+            | AssemblyError::DuplicateSymbol { span, .. }
+            | AssemblyError::RangeExceeded { span, .. }
+            | AssemblyError::UnknownMnemonic { span, .. }
+            | AssemblyError::Encoding { span, .. } => *span = Some(at.clone()),
+        }
+        self
+    }
+
+    /// Adds `note` to the end of the message, for the kinds that have one
+    pub(crate) fn with_note(mut self, note: &str) -> Self {
+        match &mut self {
+            AssemblyError::Io { message, .. }
+            | AssemblyError::Parse { message, .. }
+            | AssemblyError::RangeExceeded { message, .. }
+            | AssemblyError::Encoding { message, .. } => message.push_str(note),
+            AssemblyError::UndefinedSymbol { .. }
+            | AssemblyError::DuplicateSymbol { .. }
+            | AssemblyError::UnknownMnemonic { .. } => {}
+        }
+        self
+    }
+}
+impl Error for AssemblyError {}
+impl fmt::Display for AssemblyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AssemblyError::Io { message, .. }
+            | AssemblyError::Parse { message, .. }
+            | AssemblyError::RangeExceeded { message, .. }
+            | AssemblyError::Encoding { message, .. } => write!(f, "{}", message),
+            AssemblyError::UndefinedSymbol { name, .. } => {
+                write!(f, "Undefined label or define: {}", name)
+            }
+            AssemblyError::DuplicateSymbol {
+                name,
+                first: Some(first),
+                ..
+            } => write!(f, "Label '{}' is already defined at {}", name, first),
+            AssemblyError::DuplicateSymbol { name, .. } => {
+                write!(f, "Label '{}' is already defined by --offset-symbol", name)
+            }
+            AssemblyError::UnknownMnemonic { mnemonic, .. } => {
+                write!(f, "Unknown mnemonic '{}'", mnemonic)
+            }
+        }
+    }
+}
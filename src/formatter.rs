@@ -0,0 +1,176 @@
+use crate::asm::{
+    extract_label, split_comment, split_times, Asm, Define, DefineKind, Directive, Instruction,
+    Label, Operand,
+};
+
+const INDENT: &str = "    ";
+/// Wide enough for the longest mnemonic plus a space, so operands line up
+const MNEMONIC_WIDTH: usize = 5;
+
+fn format_operand(value: &str) -> String {
+    if Operand::is_register_name(value) {
+        value.to_uppercase()
+    } else {
+        value.to_string()
+    }
+}
+
+/// Formats a comma separated list, keeping a trailing comma so continuations still continue
+fn format_list<'a>(items: impl Iterator<Item = &'a str>, trailing_comma: bool) -> String {
+    let items: Vec<&str> = items.map(|i| i.trim()).filter(|i| !i.is_empty()).collect();
+    let mut list = items.join(", ");
+    if trailing_comma {
+        list.push(',');
+    }
+    list
+}
+
+/// Formats one line of code (no comment), returning one or more output lines
+fn format_code(code: &str) -> Vec<String> {
+    // Labels get their own line, anything after them is formatted separately
+    if let Some((label, rem_line)) = extract_label(code.to_string()) {
+        let mut lines = vec![format!("{}:", Label::from_line(label).name)];
+        if let Some(rem_line) = rem_line {
+            lines.extend(format_code(&rem_line));
+        }
+        return lines;
+    }
+
+    let split: Vec<&str> = code.split("include ").collect();
+    if split.len() > 1 {
+        let files: Vec<String> = split[1]
+            .replace('"', "")
+            .split_whitespace()
+            .map(|f| format!("\"{}\"", f))
+            .collect();
+        return vec![format!("include {}", files.join(" "))];
+    }
+
+    if let Some((count, repeated)) = split_times(code) {
+        let repeated = format_code(repeated).concat();
+        return vec![format!(
+            "{}times {} {}",
+            INDENT,
+            count,
+            repeated.trim_start()
+        )];
+    }
+
+    let trailing_comma = code.ends_with(',');
+    let first_word = code.split_whitespace().next().unwrap_or_default();
+    let line = if first_word == "define" {
+        // Malformed defines are left alone, the assembler reports them
+        let words: Vec<&str> = code.split_whitespace().collect();
+        let valid = match words.len() {
+            3 => true,
+            4 => DefineKind::from_name(words[1]).is_some(),
+            _ => false,
+        };
+        if !valid {
+            return vec![code.to_string()];
+        }
+        let define = Define::from_line(code.to_string());
+        match define.kind {
+            Some(kind) => format!(
+                "define {} {} {}",
+                kind.name(),
+                define.key,
+                format_operand(&define.value)
+            ),
+            None => format!("define {} {}", define.key, format_operand(&define.value)),
+        }
+    } else if Directive::VALID_DIRECTIVES.contains(&first_word) {
+        let directive = Directive::from_line(code.to_string());
+        if let [name, at, base] = directive.args.as_slice() {
+            if directive.mnemonic == "section" {
+                return vec![format!("section {} {} {}", name, at, format_operand(base))];
+            }
+        }
+        let args = if directive.mnemonic == "text" {
+            let quoted: Vec<String> = directive
+                .args
+                .iter()
+                .map(|a| format!("\"{}\"", a))
+                .collect();
+            format_list(quoted.iter().map(|a| a.as_str()), trailing_comma)
+        } else {
+            format_list(directive.args.iter().map(|a| a.as_str()), trailing_comma)
+        };
+        format!("{}{} {}", INDENT, directive.mnemonic, args)
+    } else {
+        let instruction = Instruction::from_line(code.to_string());
+        let args: Vec<String> = instruction
+            .args
+            .iter()
+            .map(|a| format_operand(&a.repr))
+            .collect();
+        format!(
+            "{}{:<width$}{}",
+            INDENT,
+            instruction.mnemonic.to_uppercase(),
+            format_list(args.iter().map(|a| a.as_str()), trailing_comma),
+            width = MNEMONIC_WIDTH
+        )
+    };
+    vec![line.trim_end().to_string()]
+}
+
+/// Rewrites source in the canonical style: labels, defines and includes at column zero, everything
+/// else indented with uppercase mnemonics and registers, aligned operands, and `; comment` spacing
+/// using the `comment` character. Formatting already formatted source returns it unchanged.
+pub fn format_source(source: &str, comment: char) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    let mut continuing = false;
+    for line in source.lines() {
+        let (code, comment) = match split_comment(line, comment) {
+            (code, Some(text)) => (code.trim(), Some(format!("{} {}", comment, text.trim()))),
+            (code, None) => (code.trim(), None),
+        };
+        let comment = comment.map(|c| c.trim_end().to_string());
+
+        if code.is_empty() {
+            // Blank and comment-only lines end a continuation, as they do in the assembler
+            continuing = false;
+            match comment {
+                Some(comment) if line.starts_with(char::is_whitespace) => {
+                    lines.push(format!("{}{}", INDENT, comment))
+                }
+                Some(comment) => lines.push(comment),
+                // Collapse runs of blank lines into one
+                None if lines.last().is_some_and(|l| !l.is_empty()) => lines.push(String::new()),
+                None => {}
+            }
+            continue;
+        }
+
+        // Labels and includes end a continuation too, as they do in the assembler
+        let continues =
+            continuing && extract_label(code.to_string()).is_none() && !code.contains("include ");
+        let mut formatted = if continues {
+            vec![format!(
+                "{}{}{}",
+                INDENT,
+                INDENT,
+                format_list(code.split(','), code.ends_with(','))
+            )]
+        } else {
+            format_code(code)
+        };
+        continuing = code.ends_with(',')
+            || matches!(code.to_lowercase().as_str(), "db" | "sprite16" | "audio");
+
+        if let Some(comment) = comment {
+            let last = formatted.last_mut().unwrap();
+            last.push(' ');
+            last.push_str(&comment);
+        }
+        lines.extend(formatted);
+    }
+
+    while lines.last().is_some_and(|l| l.is_empty()) {
+        lines.pop();
+    }
+    let mut formatted = lines.join("\n");
+    formatted.push('\n');
+    formatted
+}
@@ -1,260 +1,624 @@
 use crate::asm::{Instruction, Operand, ParseOperandError};
+use crate::error::AssemblyError;
+use crate::options::Target;
 
-pub struct Opcode {
+/// What an operand has to be for a mnemonic to encode it correctly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OperandKind {
+    Register,
+    Immediate,
+    Address,
+    /// A fixed name such as `I`, `DT` or `[I]`
+    Keyword(&'static str),
+}
+impl OperandKind {
+    /// Kind of one operand as written in an InstructionInfo form
+    fn from_form(operand: &'static str) -> Self {
+        match operand {
+            "Vx" | "Vy" | "V0" => OperandKind::Register,
+            "byte" | "nibble" => OperandKind::Immediate,
+            "addr" => OperandKind::Address,
+            keyword => OperandKind::Keyword(keyword),
+        }
+    }
+
+    fn matches(&self, operand: &Operand) -> bool {
+        match self {
+            OperandKind::Register => operand.is_register(),
+            OperandKind::Immediate | OperandKind::Address => !operand.is_register(),
+            OperandKind::Keyword(keyword) => operand.repr.eq_ignore_ascii_case(keyword),
+        }
+    }
+}
+impl std::fmt::Display for OperandKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            OperandKind::Register => write!(f, "a register"),
+            OperandKind::Immediate => write!(f, "an immediate"),
+            OperandKind::Address => write!(f, "an address"),
+            OperandKind::Keyword(keyword) => write!(f, "{}", keyword),
+        }
+    }
+}
+
+/// One accepted form of a mnemonic
+#[derive(Clone, Copy, Debug)]
+pub struct InstructionInfo {
+    pub mnemonic: &'static str,
+    /// Operands as written, e.g. "Vx, Vy, nibble"
+    pub form: &'static str,
+    /// Encoding with the operand fields as letters, e.g. "Dxyn"
+    pub opcode: &'static str,
+    /// Oldest target that accepts this form
+    pub target: Target,
+}
+impl InstructionInfo {
+    const fn new(mnemonic: &'static str, form: &'static str, opcode: &'static str) -> Self {
+        Self {
+            mnemonic,
+            form,
+            opcode,
+            target: Target::Chip8,
+        }
+    }
+
+    const fn on(self, target: Target) -> Self {
+        Self { target, ..self }
+    }
+
+    pub fn operand_kinds(&self) -> Vec<OperandKind> {
+        if self.form.is_empty() {
+            return Vec::new();
+        }
+        self.form.split(", ").map(OperandKind::from_form).collect()
+    }
+
+    pub fn arity(&self) -> usize {
+        self.operand_kinds().len()
+    }
+
+    fn matches(&self, operands: &[Operand]) -> bool {
+        let kinds = self.operand_kinds();
+        kinds.len() == operands.len() && kinds.iter().zip(operands).all(|(k, o)| k.matches(o))
+    }
+
+    /// Mask and value of the fixed nibbles in the first word of the opcode
+    fn pattern(&self) -> (u16, u16) {
+        self.opcode
+            .chars()
+            .take(4)
+            .fold((0, 0), |(mask, value), c| match c.to_digit(16) {
+                Some(digit) if !c.is_ascii_lowercase() => {
+                    ((mask << 4) | 0xF, (value << 4) | digit as u16)
+                }
+                _ => (mask << 4, value << 4),
+            })
+    }
+
+    /// Bytes the instruction takes, 4 when a second word follows the opcode
+    pub fn size(&self) -> usize {
+        if self.opcode.len() > 4 {
+            4
+        } else {
+            2
+        }
+    }
+
+    /// Value of the `n` field in `word`, which is a nibble or, as `nnn`, an address
+    fn n_field(&self, word: u16) -> u16 {
+        let nibbles = &self.opcode[..4];
+        let start = nibbles.find('n').unwrap_or(3);
+        let len = nibbles[start..].chars().take_while(|c| *c == 'n').count();
+        (word >> (4 * (4 - start - len))) & ((1 << (4 * len)) - 1)
+    }
+
+    /// Number of keyword operands, so `LD Vx, DT` wins over `LD Vx, byte` when both match
+    fn specificity(&self) -> usize {
+        self.operand_kinds()
+            .iter()
+            .filter(|k| matches!(k, OperandKind::Keyword(_)))
+            .count()
+    }
+}
+impl std::fmt::Display for InstructionInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        if self.form.is_empty() {
+            write!(f, "{}", self.mnemonic)
+        } else {
+            write!(f, "{} {}", self.mnemonic, self.form)
+        }
+    }
+}
+
+/// Every form, with its operands and encoding. Operands are `Vx`, `Vy` and `V0` registers, a
+/// `byte`, `nibble` or `addr` value, or a keyword written as is. Letters in the encoding are the
+/// fields the operand of the same name fills, so XO-CHIP's `LD I, Vx, Vy` and `LD Vx, Vy, I` are
+/// told apart by where the `I` keyword is and encode as 5xy2 and 5xy3. When
+/// several forms match, the one with the most keywords wins, so `LD Vx, DT` is Fx07 rather
+/// than 6xkk with DT as the byte. Forms that match equally well are listed by
+/// conflicting_forms, and the table must have none.
+const INSTRUCTIONS: &[InstructionInfo] = &[
+    InstructionInfo::new("CLS", "", "00E0"),
+    InstructionInfo::new("RET", "", "00EE"),
+    InstructionInfo::new("SYS", "addr", "0nnn"),
+    InstructionInfo::new("JP", "addr", "1nnn"),
+    InstructionInfo::new("JP", "V0, addr", "Bnnn"),
+    InstructionInfo::new("CALL", "addr", "2nnn"),
+    InstructionInfo::new("SE", "Vx, byte", "3xkk"),
+    InstructionInfo::new("SE", "Vx, Vy", "5xy0"),
+    InstructionInfo::new("SNE", "Vx, byte", "4xkk"),
+    InstructionInfo::new("SNE", "Vx, Vy", "9xy0"),
+    InstructionInfo::new("LD", "Vx, byte", "6xkk"),
+    InstructionInfo::new("LD", "Vx, Vy", "8xy0"),
+    InstructionInfo::new("LD", "I, addr", "Annn"),
+    InstructionInfo::new("LD", "Vx, DT", "Fx07"),
+    InstructionInfo::new("LD", "Vx, K", "Fx0A"),
+    InstructionInfo::new("LD", "DT, Vx", "Fx15"),
+    InstructionInfo::new("LD", "ST, Vx", "Fx18"),
+    InstructionInfo::new("LD", "F, Vx", "Fx29"),
+    InstructionInfo::new("LD", "B, Vx", "Fx33"),
+    InstructionInfo::new("LD", "[I], Vx", "Fx55"),
+    InstructionInfo::new("LD", "Vx, [I]", "Fx65"),
+    InstructionInfo::new("LD", "HF, Vx", "Fx30").on(Target::SChip),
+    InstructionInfo::new("LD", "R, Vx", "Fx75").on(Target::SChip),
+    InstructionInfo::new("LD", "Vx, R", "Fx85").on(Target::SChip),
+    InstructionInfo::new("LD", "I, Vx, Vy", "5xy2").on(Target::XoChip),
+    InstructionInfo::new("LD", "Vx, Vy, I", "5xy3").on(Target::XoChip),
+    InstructionInfo::new("LD", "I, LONG, addr", "F000 nnnn").on(Target::XoChip),
+    InstructionInfo::new("LD", "[I], audio", "F002").on(Target::XoChip),
+    InstructionInfo::new("ADD", "Vx, byte", "7xkk"),
+    InstructionInfo::new("ADD", "Vx, Vy", "8xy4"),
+    InstructionInfo::new("ADD", "I, Vx", "Fx1E"),
+    InstructionInfo::new("OR", "Vx, Vy", "8xy1"),
+    InstructionInfo::new("AND", "Vx, Vy", "8xy2"),
+    InstructionInfo::new("XOR", "Vx, Vy", "8xy3"),
+    InstructionInfo::new("SUB", "Vx, Vy", "8xy5"),
+    InstructionInfo::new("SHR", "Vx", "8x06"),
+    InstructionInfo::new("SHR", "Vx, Vy", "8xy6"),
+    InstructionInfo::new("SUBN", "Vx, Vy", "8xy7"),
+    InstructionInfo::new("SHL", "Vx", "8x0E"),
+    InstructionInfo::new("SHL", "Vx, Vy", "8xyE"),
+    InstructionInfo::new("RND", "Vx, byte", "Cxkk"),
+    InstructionInfo::new("DRW", "Vx, Vy, nibble", "Dxyn"),
+    InstructionInfo::new("SKP", "Vx", "Ex9E"),
+    InstructionInfo::new("SKNP", "Vx", "ExA1"),
+    InstructionInfo::new("NOP", "", "8000"),
+    InstructionInfo::new("MOV", "Vx, Vy", "8xy0"),
+    InstructionInfo::new("BCD", "Vx", "Fx33"),
+    InstructionInfo::new("FONT", "Vx", "Fx29"),
+    InstructionInfo::new("HIFONT", "Vx", "Fx30").on(Target::SChip),
+    InstructionInfo::new("SCD", "nibble", "00Cn").on(Target::SChip),
+    InstructionInfo::new("SCR", "", "00FB").on(Target::SChip),
+    InstructionInfo::new("SCL", "", "00FC").on(Target::SChip),
+    InstructionInfo::new("EXIT", "", "00FD").on(Target::SChip),
+    InstructionInfo::new("LOW", "", "00FE").on(Target::SChip),
+    InstructionInfo::new("HIGH", "", "00FF").on(Target::SChip),
+    InstructionInfo::new("SCU", "nibble", "00Dn").on(Target::XoChip),
+    InstructionInfo::new("PLANE", "nibble", "Fn01").on(Target::XoChip),
+];
+
+/// Every accepted mnemonic and operand form. from_instruction validates against this
+/// table, so it always matches what the assembler accepts.
+pub fn supported_instructions() -> &'static [InstructionInfo] {
+    INSTRUCTIONS
+}
+
+/// Pairs of forms of one mnemonic that some operands match equally well, so neither would be
+/// preferred. from_instruction rejects such operands rather than picking one, and the table is
+/// kept free of them.
+pub fn conflicting_forms() -> Vec<(&'static InstructionInfo, &'static InstructionInfo)> {
+    let overlap = |a: &OperandKind, b: &OperandKind| match (a, b) {
+        (OperandKind::Register, OperandKind::Register) => true,
+        (OperandKind::Keyword(a), OperandKind::Keyword(b)) => a.eq_ignore_ascii_case(b),
+        (OperandKind::Register, _) | (_, OperandKind::Register) => false,
+        // Immediates and addresses are anything but a register, keywords included
+        _ => true,
+    };
+    let mut conflicts = Vec::new();
+    for (i, a) in INSTRUCTIONS.iter().enumerate() {
+        for b in &INSTRUCTIONS[i + 1..] {
+            let (kinds_a, kinds_b) = (a.operand_kinds(), b.operand_kinds());
+            if a.mnemonic == b.mnemonic
+                && kinds_a.len() == kinds_b.len()
+                && a.specificity() == b.specificity()
+                && kinds_a.iter().zip(&kinds_b).all(|(a, b)| overlap(a, b))
+            {
+                conflicts.push((a, b));
+            }
+        }
+    }
+    conflicts
+}
+
+/// Form of the instruction at the start of `bytes`, choosing the one with the most fixed bits
+/// when several match and the first listed on a tie. Returns None for words that are not a
+/// known instruction on `target`.
+pub fn decode_info(bytes: &[u8], target: Target) -> Option<&'static InstructionInfo> {
+    let word = u16::from_be_bytes([*bytes.first()?, *bytes.get(1)?]);
+    let info = INSTRUCTIONS
+        .iter()
+        .filter(|i| i.target <= target && i.size() <= bytes.len())
+        .filter(|i| {
+            let (mask, value) = i.pattern();
+            word & mask == value
+        })
+        .max_by_key(|i| i.pattern().0.count_ones())?;
+    // Prefer the first listed form on a tie, e.g. LD Vx, Vy over MOV
+    INSTRUCTIONS
+        .iter()
+        .find(|i| i.target <= target && i.opcode == info.opcode)
+        .or(Some(info))
+}
+
+/// Decodes the instruction at the start of `bytes` into source text and its size in bytes,
+/// as picked by `decode_info`
+pub fn decode(bytes: &[u8], target: Target) -> Option<(String, usize)> {
+    decode_with_names(bytes, target, |_| None)
+}
+
+/// Like `decode`, but writes an address operand as the name `name_of` gives it, or in hex when
+/// it gives none. Byte immediates are written in decimal.
+pub fn decode_with_names(
+    bytes: &[u8],
+    target: Target,
+    name_of: impl Fn(usize) -> Option<String>,
+) -> Option<(String, usize)> {
+    let info = decode_info(bytes, target)?;
+    let word = u16::from_be_bytes([bytes[0], bytes[1]]);
+
+    let operands: Vec<String> = info
+        .form
+        .split(", ")
+        .filter(|o| !o.is_empty())
+        .map(|o| match o {
+            "Vx" => format!("V{:X}", (word >> 8) & 0xF),
+            "Vy" => format!("V{:X}", (word >> 4) & 0xF),
+            "byte" => (word & 0xFF).to_string(),
+            "nibble" => info.n_field(word).to_string(),
+            "addr" if info.size() == 4 => {
+                let address = u16::from_be_bytes([bytes[2], bytes[3]]);
+                name_of(address as usize).unwrap_or_else(|| format!("{:#06x}", address))
+            }
+            "addr" => {
+                let address = word & 0xFFF;
+                name_of(address as usize).unwrap_or_else(|| format!("{:#05x}", address))
+            }
+            keyword => keyword.to_string(),
+        })
+        .collect();
+    let text = if operands.is_empty() {
+        info.mnemonic.to_string()
+    } else {
+        format!("{} {}", info.mnemonic, operands.join(", "))
+    };
+    Some((text, info.size()))
+}
+
+/// Borrows its operands from the Instruction it was built from
+pub struct Opcode<'a> {
     base: u16,
-    vx: Option<Operand>,
-    vy: Option<Operand>,
-    nnn: Option<Operand>,
-    kk: Option<Operand>,
-    n: Option<Operand>,
+    vx: Option<&'a Operand>,
+    vy: Option<&'a Operand>,
+    nnn: Option<&'a Operand>,
+    kk: Option<&'a Operand>,
+    n: Option<&'a Operand>,
+    /// Address word following the opcode in XO-CHIP's `LD I, LONG nnnn`
+    long: Option<&'a Operand>,
 }
-impl Opcode {
+impl<'a> Opcode<'a> {
     fn new(base: u16) -> Self {
         Self {
-            base: base,
+            base,
             vx: None,
             vy: None,
             nnn: None,
             kk: None,
             n: None,
+            long: None,
         }
     }
 
-    fn set_vx(self, value: Operand) -> Self {
+    fn set_vx(self, value: &'a Operand) -> Self {
         Opcode {
             vx: Some(value),
             ..self
         }
     }
-    fn set_vy(self, value: Operand) -> Self {
+    fn set_vy(self, value: &'a Operand) -> Self {
         Opcode {
             vy: Some(value),
             ..self
         }
     }
-    fn set_nnn(self, value: Operand) -> Self {
+    fn set_nnn(self, value: &'a Operand) -> Self {
         Opcode {
             nnn: Some(value),
             ..self
         }
     }
-    fn set_kk(self, value: Operand) -> Self {
+    fn set_kk(self, value: &'a Operand) -> Self {
         Opcode {
             kk: Some(value),
             ..self
         }
     }
-    fn set_n(self, value: Operand) -> Self {
+    fn set_n(self, value: &'a Operand) -> Self {
         Opcode {
             n: Some(value),
             ..self
         }
     }
+    fn set_long(self, value: &'a Operand) -> Self {
+        Opcode {
+            long: Some(value),
+            ..self
+        }
+    }
+
+    /// Opcode for `info` with the fixed nibbles of its encoding, and each operand set to the
+    /// field its form names. Keyword operands such as `DT` only select the form.
+    fn from_form(info: &InstructionInfo, operands: &'a [Operand]) -> Self {
+        let mut opcode = Opcode::new(info.pattern().1);
+        for (form, operand) in info.form.split(", ").zip(operands) {
+            opcode = match form {
+                "Vx" => opcode.set_vx(operand),
+                "Vy" => opcode.set_vy(operand),
+                "byte" => opcode.set_kk(operand),
+                // PLANE's mask sits where a register usually does, as Fn01
+                "nibble" if info.opcode.find('n') == Some(1) => opcode.set_vx(operand),
+                "nibble" => opcode.set_n(operand),
+                "addr" if info.size() == 4 => opcode.set_long(operand),
+                "addr" => opcode.set_nnn(operand),
+                _ => opcode,
+            };
+        }
+        opcode
+    }
 
     pub fn to_bytes(&self) -> Result<u16, ParseOperandError> {
         let nnn = match &self.nnn {
-            Some(value) => Some(value.clone().parse()?),
+            Some(value) => Some(value.parse()?),
             None => None,
         };
         let vx = match &self.vx {
-            Some(value) => Some(value.clone().parse()?),
+            Some(value) => Some(value.parse()?),
             None => None,
         };
         let vy = match &self.vy {
-            Some(value) => Some(value.clone().parse()?),
+            Some(value) => Some(value.parse()?),
             None => None,
         };
         let kk = match &self.kk {
-            Some(value) => Some(value.clone().parse()?),
+            Some(value) => Some(value.parse()?),
             None => None,
         };
         let n = match &self.n {
-            Some(value) => Some(value.clone().parse()?),
+            Some(value) => Some(value.parse()?),
             None => None,
         };
 
         let bytes: u16 = match (nnn, vx, vy, kk, n) {
             (Some(nnn), None, None, None, None) => self.base | nnn,
-            (None, Some(vx), None, None, None) => self.base | (vx as u16) << 0x8,
-            (None, Some(vx), Some(vy), None, None) => {
-                self.base | (vx as u16) << 0x8 | (vy as u16) << 0x4
-            }
-            (None, Some(vx), None, Some(kk), None) => self.base | (vx as u16) << 0x8 | (kk as u16),
-            (None, Some(vx), Some(vy), None, Some(n)) => {
-                self.base | (vx as u16) << 0x8 | (vy as u16) << 0x4 | (n as u16)
-            }
-            (None, None, None, None, Some(n)) => self.base | (n as u16),
+            (None, Some(vx), None, None, None) => self.base | (vx << 0x8),
+            (None, Some(vx), Some(vy), None, None) => self.base | (vx << 0x8) | (vy << 0x4),
+            (None, Some(vx), None, Some(kk), None) => self.base | (vx << 0x8) | kk,
+            (None, Some(vx), Some(vy), None, Some(n)) => self.base | (vx << 0x8) | (vy << 0x4) | n,
+            (None, None, None, None, Some(n)) => self.base | n,
             (None, None, None, None, None) => self.base,
             (_, _, _, _, _) => {
-                return Err(ParseOperandError {
-                    message: format!("Invalid opcode: {:?}", self),
-                })
+                return Err(ParseOperandError::new(format!(
+                    "Invalid opcode: {:?}",
+                    self
+                )))
             }
         };
 
         Ok(bytes)
     }
 
-    pub fn from_instruction(instruction: Instruction) -> Option<Opcode> {
-        let mnemonic = instruction.mnemonic;
-        let operands = instruction.args;
+    /// Encodes every word of the instruction, which is two for `LD I, LONG nnnn`
+    pub fn to_words(&self) -> Result<Vec<u16>, ParseOperandError> {
+        let mut words = vec![self.to_bytes()?];
+        if let Some(long) = self.long {
+            words.push(long.parse()?);
+        }
+        Ok(words)
+    }
 
-        let opcode = match mnemonic.to_uppercase().as_str() {
-            "CLS" => Opcode::new(0x00E0),
-            "RET" => Opcode::new(0x00EE),
-            "SYS" => Opcode::new(0x0000).set_nnn(operands[0].clone()),
-            "JP" => match operands[0].repr.as_str() {
-                "V0" | "v0" => Opcode::new(0xB000).set_nnn(operands[1].clone()),
-                _ => Opcode::new(0x1000).set_nnn(operands[0].clone()),
-            },
-            "CALL" => Opcode::new(0x2000).set_nnn(operands[0].clone()),
-            "SE" => match operands[1].is_register() {
-                true => Opcode::new(0x5000)
-                    .set_vx(operands[0].clone())
-                    .set_vy(operands[1].clone()),
-                false => Opcode::new(0x3000)
-                    .set_vx(operands[0].clone())
-                    .set_kk(operands[1].clone()),
-            },
-            "SCD" => {
-                //SCD nibble
-                Opcode::new(0x00C0).set_n(operands[0].clone())
-            }
-            "SCR" => {
-                //SCR
-                Opcode::new(0x00FB)
-            }
-            "SCL" => {
-                //SCL
-                Opcode::new(0x00FC)
-            }
-            "EXIT" => {
-                //EXIT
-                Opcode::new(0x00FD)
-            }
-            "LOW" => {
-                //LOW
-                Opcode::new(0x00FE)
-            }
-            "HIGH" => {
-                //HIGH
-                Opcode::new(0x00FF)
+    pub fn from_instruction(
+        instruction: &'a Instruction,
+        target: Target,
+    ) -> Result<Opcode<'a>, AssemblyError> {
+        let mnemonic = instruction.mnemonic.to_uppercase();
+        let operands = &instruction.args;
+        // Describes the operands by kind, e.g. "Vx, K", for error messages
+        let form = || {
+            operands
+                .iter()
+                .map(|o| match (o.is_register(), o.parse()) {
+                    (true, _) => "Vx".to_string(),
+                    (false, Ok(_)) => "imm".to_string(),
+                    (false, Err(_)) => o.repr.clone(),
+                })
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+        let invalid = || {
+            AssemblyError::encoding(format!(
+                "{}: unsupported operand combination {} with {} operands",
+                mnemonic,
+                form(),
+                operands.len()
+            ))
+        };
+        let operand = |i: usize| {
+            operands.get(i).ok_or_else(|| {
+                AssemblyError::encoding(format!(
+                    "{} expects at least {} operands, found {}",
+                    mnemonic,
+                    i + 1,
+                    operands.len()
+                ))
+            })
+        };
+
+        let forms: Vec<&InstructionInfo> = INSTRUCTIONS
+            .iter()
+            .filter(|i| i.mnemonic == mnemonic)
+            .collect();
+        if forms.is_empty() {
+            return Err(AssemblyError::UnknownMnemonic {
+                span: None,
+                mnemonic: instruction.mnemonic.clone(),
+            });
+        }
+        if let Some(bad) = operands.iter().find(|o| o.is_malformed_register()) {
+            let e = Operand::parse_register_str(&bad.repr).unwrap_err();
+            return Err(
+                AssemblyError::encoding(format!("{}: {}", mnemonic, e)).with_token(&bad.repr)
+            );
+        }
+        let candidates: Vec<&InstructionInfo> = forms
+            .iter()
+            .copied()
+            .filter(|f| f.arity() == operands.len())
+            .collect();
+        if candidates.is_empty() {
+            if forms.iter().all(|f| f.arity() == 0) {
+                return Err(AssemblyError::encoding(format!(
+                    "{} takes no operands, got {}",
+                    mnemonic,
+                    operands.len()
+                )));
             }
-            "DRW" => {
-                //DRW Vx, Vy, nibble
-                Opcode::new(0xD000)
-                    .set_vx(operands[0].clone())
-                    .set_vy(operands[1].clone())
-                    .set_n(operands[2].clone())
+            let mut arities: Vec<usize> = forms.iter().map(|f| f.arity()).collect();
+            arities.sort();
+            arities.dedup();
+            let arities: Vec<String> = arities.iter().map(|a| a.to_string()).collect();
+            return Err(AssemblyError::encoding(format!(
+                "{} expects {} operands, found {}",
+                mnemonic,
+                arities.join(" or "),
+                operands.len()
+            )));
+        }
+        let matching: Vec<&InstructionInfo> = candidates
+            .iter()
+            .copied()
+            .filter(|f| f.matches(operands))
+            .collect();
+        let top = matching.iter().map(|f| f.specificity()).max();
+        let mut best: Vec<&InstructionInfo> = matching
+            .into_iter()
+            .filter(|f| Some(f.specificity()) == top)
+            .collect();
+        if let [first, second, ..] = best[..] {
+            // conflicting_forms keeps the table free of these, so this is never left to order
+            return Err(AssemblyError::encoding(format!(
+                "{}: operands match both {} and {}",
+                mnemonic, first, second
+            )));
+        }
+        let info = match best.pop() {
+            Some(info) => info,
+            // Point at an operand that does not fit, when every possible form wants the same
+            // kind there, such as the register of `SE 5, 5`
+            None => {
+                let mismatch = operands.iter().enumerate().find_map(|(i, operand)| {
+                    let kind = candidates[0].operand_kinds()[i];
+                    let agreed = candidates.iter().all(|f| f.operand_kinds()[i] == kind);
+                    (agreed && !kind.matches(operand)).then_some((i, kind, operand))
+                });
+                return Err(match mismatch {
+                    Some((i, kind, operand)) => AssemblyError::encoding(format!(
+                        "{} operand {} must be {}, found {}",
+                        mnemonic,
+                        i + 1,
+                        kind,
+                        operand.repr
+                    ))
+                    .with_token(operand.written()),
+                    None => invalid(),
+                });
             }
-            "LD" => {
-                match (
-                    operands[0].is_register(),
-                    operands[1].is_register(),
-                    operands[0].repr.as_str(),
-                    operands[1].repr.as_str(),
-                    operands.len(),
-                ) {
-                    (true, true, _, _, 2) => Opcode::new(0x8000)
-                        .set_vx(operands[0].clone())
-                        .set_vy(operands[1].clone()),
-                    (true, _, _, "R", 2) => Opcode::new(0xF085).set_vx(operands[0].clone()),
-                    (true, _, _, "DT", 2) => Opcode::new(0xF007).set_vx(operands[0].clone()),
-                    (true, _, _, "K", 2) => Opcode::new(0xF00A).set_vx(operands[0].clone()),
-                    (true, _, _, "[I]", 2) => Opcode::new(0xF065).set_vx(operands[0].clone()),
-                    (true, _, _, _, 2) => Opcode::new(0x6000)
-                        .set_vx(operands[0].clone())
-                        .set_kk(operands[1].clone()),
-                    (false, true, "HF", _, 2) => Opcode::new(0xF030).set_vx(operands[1].clone()),
-                    (false, true, "R", _, 2) => Opcode::new(0xF075).set_vx(operands[1].clone()),
-                    (false, true, "ST", _, 2) => Opcode::new(0xF018).set_vx(operands[1].clone()),
-                    (false, true, "F", _, 2) => Opcode::new(0xF029).set_vx(operands[1].clone()),
-                    (false, true, "B", _, 2) => Opcode::new(0xF033).set_vx(operands[1].clone()),
-                    (false, true, "[I]", _, 2) => Opcode::new(0xF055).set_vx(operands[1].clone()),
-                    (false, false, "I", _, 2) => Opcode::new(0xA000).set_nnn(operands[1].clone()),
-                    (false, true, _, _, 2) => Opcode::new(0xF015).set_vx(operands[1].clone()),
-                    (true, true, _, _, 3) => match operands[2].repr.as_str() {
-                        "I" => Opcode::new(0x5001)
-                            .set_vx(operands[0].clone())
-                            .set_vy(operands[1].clone()),
-                        _ => return None,
-                    },
-                    (false, true, _, _, 3) => match operands[0].repr.as_str() {
-                        "I" => Opcode::new(0x5002)
-                            .set_vx(operands[1].clone())
-                            .set_vy(operands[2].clone()),
-                        _ => return None,
-                    },
-                    (_, _, _, _, _) => return None,
+        };
+        if info.target > target {
+            return Err(AssemblyError::encoding(format!(
+                "{} requires --target {}",
+                info,
+                info.target.name()
+            )));
+        }
+        // Each field only has so many bits, anything larger would spill into the rest of the opcode
+        for (i, (form, operand)) in info.form.split(", ").zip(operands).enumerate() {
+            let (max, field) = match form {
+                "byte" => (0xFF, "a byte (0 to 255)"),
+                "nibble" => (0xF, "a nibble (0 to 15)"),
+                // LONG has all 16 bits, and LD I, addr names where a large address came from below
+                "addr" if info.size() == 2 && info.form != "I, addr" => {
+                    (0xFFF, "an address (0 to 0xFFF)")
                 }
+                _ => continue,
+            };
+            if operand.parse().is_ok_and(|n| n > max) {
+                return Err(AssemblyError::range(format!(
+                    "{} operand {} must be {}, found {}",
+                    mnemonic,
+                    i + 1,
+                    field,
+                    operand.repr
+                ))
+                .with_token(operand.written()));
             }
-            "SNE" => match operands[1].is_register() {
-                true => Opcode::new(0x9000)
-                    .set_vx(operands[0].clone())
-                    .set_vy(operands[1].clone()),
-                false => Opcode::new(0x4000)
-                    .set_vx(operands[0].clone())
-                    .set_kk(operands[1].clone()),
-            },
-            "ADD" => match (operands[0].is_register(), operands[1].is_register()) {
-                (true, false) => Opcode::new(0x7000)
-                    .set_vx(operands[0].clone())
-                    .set_kk(operands[1].clone()),
-                (false, true) => Opcode::new(0xF01E).set_vx(operands[1].clone()),
-                (_, _) => Opcode::new(0x8004)
-                    .set_vx(operands[0].clone())
-                    .set_vy(operands[1].clone()),
-            },
-            "OR" => Opcode::new(0x8001)
-                .set_vx(operands[0].clone())
-                .set_vy(operands[1].clone()),
-            "AND" => Opcode::new(0x8002)
-                .set_vx(operands[0].clone())
-                .set_vy(operands[1].clone()),
-            "XOR" => Opcode::new(0x8003)
-                .set_vx(operands[0].clone())
-                .set_vy(operands[1].clone()),
-            "SUB" => Opcode::new(0x8005)
-                .set_vx(operands[0].clone())
-                .set_vy(operands[1].clone()),
-            "SHR" => {
-                if operands.len() == 1 {
-                    Opcode::new(0x8006).set_vx(operands[0].clone())
-                } else {
-                    Opcode::new(0x8006)
-                        .set_vx(operands[0].clone())
-                        .set_vy(operands[1].clone())
+        }
+
+        // The table binds every operand to its field, these are the limits it cannot express
+        match info.form {
+            // Bnnn jumps relative to V0 and cannot use any other register
+            "V0, addr" if !operand(0)?.repr.eq_ignore_ascii_case("V0") => {
+                return Err(
+                    AssemblyError::encoding("JP Vx, addr only supports V0".to_string())
+                        .with_token(operand(0)?.written()),
+                );
+            }
+            "I, addr" => {
+                let address = operand(1)?;
+                // Annn only has room for 12 bits, anything above would spill into the opcode
+                if let Ok(n @ 0x1000..) = address.parse() {
+                    let symbol = match &address.symbol {
+                        Some(symbol) => format!(", from '{}'", symbol),
+                        None => String::new(),
+                    };
+                    return Err(AssemblyError::range(format!(
+                        "LD I: address {:#x} exceeds 12 bits{}",
+                        n, symbol
+                    ))
+                    .with_token(address.written()));
                 }
             }
-            "SUBN" => Opcode::new(0x8007)
-                .set_vx(operands[0].clone())
-                .set_vy(operands[1].clone()),
-            "SHL" => {
-                if operands.len() == 1 {
-                    Opcode::new(0x800E).set_vx(operands[0].clone())
-                } else {
-                    Opcode::new(0x800E)
-                        .set_vx(operands[0].clone())
-                        .set_vy(operands[1].clone())
+            _ if info.mnemonic == "PLANE" => {
+                // A mask of the two planes
+                let n = operand(0)?;
+                if n.parse().is_ok_and(|n| n > 3) {
+                    return Err(AssemblyError::range(format!(
+                        "PLANE mask out of range (0 to 3): {}",
+                        n.repr
+                    ))
+                    .with_token(n.written()));
                 }
             }
-            "RND" => Opcode::new(0xC000)
-                .set_vx(operands[0].clone())
-                .set_kk(operands[1].clone()),
-            "SKP" => Opcode::new(0xE09E).set_vx(operands[0].clone()),
-            "SKNP" => Opcode::new(0xE0A1).set_vx(operands[0].clone()),
-            _ => return None,
-        };
+            _ => {}
+        }
 
-        Some(opcode)
+        Ok(Opcode::from_form(info, operands))
     }
 }
-impl std::fmt::Debug for Opcode {
+impl std::fmt::Debug for Opcode<'_> {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         write!(
             f,
-            "{{ base: {:#06x}, vx: {:?}, vy: {:?}, nnn: {:?}, kk: {:?}, n: {:?} }}",
-            self.base, self.vx, self.vy, self.nnn, self.kk, self.n
+            "{{ base: {:#06x}, vx: {:?}, vy: {:?}, nnn: {:?}, kk: {:?}, n: {:?}, long: {:?} }}",
+            self.base, self.vx, self.vy, self.nnn, self.kk, self.n, self.long
         )
     }
 }
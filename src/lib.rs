@@ -0,0 +1,11 @@
+pub mod asm;
+pub mod diagnostics;
+pub mod disassemble;
+pub mod error;
+pub mod formatter;
+pub mod instructions;
+pub mod options;
+pub mod output;
+pub mod rename;
+pub mod repl;
+pub mod report;
@@ -1,28 +1,770 @@
+use std::collections::HashMap;
 use std::env;
-use std::io::Write;
+use std::fs::File;
+use std::io::{self, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::process;
+use std::sync::mpsc;
+use std::time::Duration;
 
-mod asm;
-use asm::generate_full_asm;
+use notify::{RecursiveMode, Watcher};
 
-mod instructions;
+use chip8_assembler::asm::{
+    expand, generate_full_asm, generate_full_asm_cached, print_tree, Operand, ParseCache,
+};
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::formatter::format_source;
+use chip8_assembler::options::{AssemblerOptions, ShiftQuirk, Target};
+use chip8_assembler::output::{ChecksumKind, Format, WordWidth};
+use chip8_assembler::{disassemble, instructions, rename, repl, report};
+
+const USAGE: &str = "Usage: cargo run [--check] [--verbose | --quiet] [--werror] [--verify] [--no-color] [--diagnostics human|json] [--diff 'old/asm' 'new/asm'] [--watch] [--fmt] [--rename OLD=NEW] [--check-sprites] [--check-draws] [--check-flags] [--xref] [--map] [--stats] [--registers] [--coverage] [--listing [--pack-words]] [--emit dot] [--list-instructions] [--repl] [--expand] [--print-tree] [--comment-char c] [--offset-symbol name] [--entry label [--entry-jump]] [--shift-quirk legacy|modern] [--target chip8|schip|xochip] [--format raw|ihex|srec|carray|python|base64|hexdump|coe|mif|readmemh] [--word-width 8|16] [--c-attribute attr] [--pad-to size] [--fill byte] [--max-rom-size size] [--checksum sum16|crc16] [--source-map 'path'] [--symbol-map 'path'] [--breakpoints 'path'] [--disassemble 'rom' [offset]] [--decode word ...] [--verify-checksum sum16|crc16 'rom' ...] 'path/to/asm' ['more/asm' ...] 'path/to/out' [offset, e.g. 512 or 0x200]";
+
+/// Exit code for assembly errors (bad source, missing files, failed writes)
+const EXIT_ERROR: i32 = 1;
+/// Exit code for invalid command line usage
+const EXIT_USAGE: i32 = 2;
+
+/// How long to wait for an editor to finish writing before reassembling
+const WATCH_DEBOUNCE: Duration = Duration::from_millis(100);
+
+struct Cli {
+    paths: Vec<String>,
+    out_path: Option<String>,
+    options: AssemblerOptions,
+    format: Format,
+    /// Size to zero-fill the output up to
+    pad_to: Option<usize>,
+    checksum: Option<ChecksumKind>,
+    /// Where to write the CSV source map, if anywhere
+    source_map: Option<String>,
+    /// Where to write the CSV symbol map, if anywhere
+    symbol_map: Option<String>,
+    /// Where to write the CSV of code labels for emulator breakpoints, if anywhere
+    breakpoints: Option<String>,
+    /// Also print the files read and the assembled entries to stderr
+    verbose: bool,
+    /// Print errors only, without warnings
+    quiet: bool,
+    /// Fail on warnings as if they were errors
+    werror: bool,
+    /// Decode and re-encode the output to check the assembler round trips
+    verify: bool,
+    /// Color diagnostics with ANSI escapes
+    color: bool,
+    /// Print diagnostics as a JSON array instead of for people
+    json_diagnostics: bool,
+    xref: bool,
+    map: bool,
+    stats: bool,
+    /// Print the registers each routine reads and writes
+    registers: bool,
+    /// Print the control flow graph as GraphViz DOT
+    emit_dot: bool,
+    /// Print which opcodes the program uses and which it does not
+    coverage: bool,
+    /// Print an assembly listing
+    listing: bool,
+    /// Show data as 16-bit words in the listing
+    pack_words: bool,
+}
+
+/// Assembles once, printing diagnostics to stderr and writing the output unless in check mode.
+/// Files unchanged since they went into `cache` are not parsed again. Returns every source file
+/// that was read and whether assembly succeeded.
+fn assemble(cli: &Cli, cache: &mut ParseCache) -> (Vec<String>, bool) {
+    let mut diagnostics = Diagnostics::new();
+    let full_asm = generate_full_asm_cached(&cli.paths, &cli.options, &mut diagnostics, cache);
+    let mut bytes = full_asm.to_bytes(&mut diagnostics);
+    if cli.verify && !diagnostics.has_errors() {
+        if let Err(e) = full_asm.verify(&bytes) {
+            diagnostics.error(format!("Verify failed at {}", e));
+        }
+    }
+    // Padding leaves room for the checksum so it stays the last thing in the ROM
+    let footer = cli.checksum.map_or(0, |_| ChecksumKind::SIZE);
+    if let Some(size) = cli.pad_to {
+        if bytes.len() + footer > size {
+            diagnostics.error(format!(
+                "Program is {} bytes, larger than --pad-to {}",
+                bytes.len() + footer,
+                size
+            ));
+        }
+        bytes.resize(
+            bytes.len().max(size.saturating_sub(footer)),
+            full_asm.options.fill,
+        );
+    }
+    if let Some(checksum) = cli.checksum {
+        checksum.append(&mut bytes);
+    }
+    if cli.werror {
+        diagnostics.promote_warnings();
+    }
+
+    // Reports go to stderr when the program itself is written to stdout
+    let to_stdout = cli.out_path.as_deref() == Some("-");
+    let show = |text: String| {
+        if to_stdout {
+            eprint!("{}", text);
+        } else {
+            print!("{}", text);
+        }
+    };
+    if cli.verbose {
+        eprintln!("Files: {}", full_asm.files.join(", "));
+        eprint!("{}", full_asm);
+        eprint!("{}", report::sprites(&full_asm));
+    }
+    if cli.xref {
+        show(report::xref(&full_asm));
+    }
+    if cli.map {
+        show(report::memory_map(&full_asm));
+    }
+    if cli.stats {
+        show(report::stats(&full_asm));
+    }
+    if cli.registers {
+        show(report::register_usage(&full_asm));
+    }
+    if cli.emit_dot {
+        show(report::control_flow_dot(&full_asm));
+    }
+    if cli.coverage {
+        show(report::opcode_coverage(&full_asm, &bytes));
+    }
+    if cli.listing {
+        show(report::listing(&full_asm, &bytes, cli.pack_words));
+    }
+
+    if cli.quiet {
+        diagnostics.drop_warnings();
+    }
+    if cli.json_diagnostics {
+        eprintln!("{}", diagnostics.to_json());
+    } else {
+        for diagnostic in diagnostics.items.iter() {
+            eprint!("{}", diagnostic.render(cli.color, cli.options.comment));
+        }
+    }
+    if diagnostics.has_errors() {
+        return (full_asm.files, false);
+    }
+
+    // Check mode only validates the program, nothing is written, not even the maps
+    let out_path = match &cli.out_path {
+        Some(out_path) => out_path,
+        None => return (full_asm.files, true),
+    };
+
+    if let Some(path) = &cli.source_map {
+        if let Err(e) = std::fs::write(path, report::source_map(&full_asm)) {
+            eprintln!("error: Unable to write {}: {}", path, e);
+            return (full_asm.files, false);
+        }
+    }
+    if let Some(path) = &cli.symbol_map {
+        if let Err(e) = std::fs::write(path, report::symbol_map(&full_asm)) {
+            eprintln!("error: Unable to write {}: {}", path, e);
+            return (full_asm.files, false);
+        }
+    }
+    if let Some(path) = &cli.breakpoints {
+        if let Err(e) = std::fs::write(path, report::breakpoints(&full_asm)) {
+            eprintln!("error: Unable to write {}: {}", path, e);
+            return (full_asm.files, false);
+        }
+    }
+
+    // Write to file
+    if let Err(e) = open_output(out_path).and_then(|mut out| {
+        out.write_all(&cli.format.serialize(&bytes, cli.options.offset))?;
+        out.flush()
+    }) {
+        eprintln!("error: Unable to write {}: {}", out_path, e);
+        return (full_asm.files, false);
+    }
+    (full_asm.files, true)
+}
+
+/// Opens a buffered writer for the output, where `-` means stdout
+fn open_output(path: &str) -> io::Result<Box<dyn Write>> {
+    if path == "-" {
+        Ok(Box::new(BufWriter::new(io::stdout().lock())))
+    } else {
+        Ok(Box::new(BufWriter::new(File::create(path)?)))
+    }
+}
+
+/// Rewrites every file in the canonical style, returning whether all of them could be formatted
+fn format_files(paths: &[String], comment: char) -> bool {
+    let mut ok = true;
+    for path in paths {
+        let result = std::fs::read_to_string(path).and_then(|source| {
+            let formatted = format_source(&source, comment);
+            if formatted == source {
+                Ok(())
+            } else {
+                std::fs::write(path, formatted)
+            }
+        });
+        if let Err(e) = result {
+            eprintln!("error: Unable to format {}: {}", path, e);
+            ok = false;
+        }
+    }
+    ok
+}
+
+/// Renames a label or define everywhere in the program and its includes, rewriting the files.
+/// Returns whether it succeeded.
+fn rename_symbol(paths: &[String], options: &AssemblerOptions, old: &str, new: &str) -> bool {
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm(paths, options, &mut diagnostics);
+    if diagnostics.has_errors() {
+        for diagnostic in diagnostics.items.iter() {
+            eprint!("{}", diagnostic.render(false, options.comment));
+        }
+        eprintln!("error: Fix the errors above before renaming");
+        return false;
+    }
+    let rewritten = match rename::rename(&assembly, old, new) {
+        Ok(rewritten) => rewritten,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            return false;
+        }
+    };
+    for (path, source) in rewritten {
+        if let Err(e) = std::fs::write(&path, source) {
+            eprintln!("error: Unable to write {}: {}", path, e);
+            return false;
+        }
+    }
+    true
+}
+
+/// Prints the disassembly of a ROM, naming addresses from a symbol map when one is given.
+/// Returns whether it succeeded.
+fn disassemble_rom(
+    rom_path: &str,
+    symbol_map: Option<&String>,
+    options: &AssemblerOptions,
+) -> bool {
+    let bytes = match std::fs::read(rom_path) {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("error: Unable to read {}: {}", rom_path, e);
+            return false;
+        }
+    };
+    let symbols = match symbol_map.map(|path| {
+        std::fs::read_to_string(path)
+            .map_err(|e| format!("Unable to read {}: {}", path, e))
+            .and_then(|text| disassemble::parse_symbol_map(&text))
+    }) {
+        Some(Ok(symbols)) => symbols,
+        Some(Err(e)) => {
+            eprintln!("error: {}", e);
+            return false;
+        }
+        None => Default::default(),
+    };
+    print!(
+        "{}",
+        disassemble::disassemble(&bytes, options.offset, &symbols, options.target)
+    );
+    true
+}
+
+/// Prints what each opcode word decodes to, one instruction to a line. A LONG instruction
+/// takes the word after it as its address. Returns whether every word was an instruction.
+fn decode_words(words: &[String], target: Target) -> bool {
+    let mut bytes: Vec<u8> = Vec::new();
+    for word in words {
+        match Operand::parse_numeric_str(word) {
+            Ok(value) => bytes.extend(value.to_be_bytes()),
+            Err(_) => usage_error(format!("Invalid opcode word: {}", word)),
+        }
+    }
+    let mut at = 0;
+    while at < bytes.len() {
+        match instructions::decode(&bytes[at..], target) {
+            Some((text, size)) => {
+                println!("{}", text);
+                at += size;
+            }
+            None => {
+                eprintln!(
+                    "error: {:#06x} is not an instruction on {}",
+                    u16::from_be_bytes([bytes[at], bytes[at + 1]]),
+                    target.name()
+                );
+                return false;
+            }
+        }
+    }
+    true
+}
+
+/// Reassembles every time one of the sources changes, parsing only the files that did. Errors
+/// are reported but never stop the loop.
+fn watch(cli: &Cli) -> notify::Result<()> {
+    let (tx, rx) = mpsc::channel();
+    let mut watcher = notify::recommended_watcher(tx)?;
+    let mut watched: Vec<String> = Vec::new();
+    let mut cache = ParseCache::default();
+    loop {
+        let (files, ok) = assemble(cli, &mut cache);
+        if ok {
+            eprintln!("Assembled successfully, watching for changes...");
+        } else {
+            eprintln!("Assembly failed, watching for changes...");
+        }
+
+        // Includes may have changed, so rewatch the files from this run
+        for file in watched.drain(..) {
+            let _ = watcher.unwatch(Path::new(&file));
+        }
+        for file in files {
+            watcher.watch(Path::new(&file), RecursiveMode::NonRecursive)?;
+            watched.push(file);
+        }
+
+        // Block for the first change, then swallow the burst that usually follows a save
+        loop {
+            match rx.recv() {
+                Ok(Ok(event)) if !event.kind.is_access() => break,
+                Ok(Err(e)) => return Err(e),
+                Ok(_) => {}
+                Err(_) => return Ok(()),
+            }
+        }
+        while rx.recv_timeout(WATCH_DEBOUNCE).is_ok() {}
+    }
+}
+
+/// Flags that take a value, given as `--name value` or `--name=value`
+const VALUE_FLAGS: [&str; 19] = [
+    "--shift-quirk",
+    "--target",
+    "--format",
+    "--word-width",
+    "--c-attribute",
+    "--pad-to",
+    "--fill",
+    "--checksum",
+    "--verify-checksum",
+    "--source-map",
+    "--emit",
+    "--diagnostics",
+    "--comment-char",
+    "--offset-symbol",
+    "--rename",
+    "--symbol-map",
+    "--max-rom-size",
+    "--breakpoints",
+    "--entry",
+];
+
+/// Flags that are on or off, given as `--name`
+const SWITCHES: [&str; 26] = [
+    "--check",
+    "--check-draws",
+    "--check-flags",
+    "--check-sprites",
+    "--coverage",
+    "--decode",
+    "--diff",
+    "--disassemble",
+    "--entry-jump",
+    "--expand",
+    "--fmt",
+    "--list-instructions",
+    "--listing",
+    "--map",
+    "--no-color",
+    "--pack-words",
+    "--print-tree",
+    "--quiet",
+    "--registers",
+    "--repl",
+    "--stats",
+    "--verbose",
+    "--verify",
+    "--watch",
+    "--werror",
+    "--xref",
+];
+
+fn usage_error(message: String) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!("{}", USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+/// Assembles two programs and prints where their bytes differ. Returns whether they are identical.
+fn diff_programs(old_path: &str, new_path: &str, options: &AssemblerOptions, color: bool) -> bool {
+    let mut diagnostics = Diagnostics::new();
+    let old = generate_full_asm(&[old_path.to_string()], options, &mut diagnostics);
+    let old_bytes = old.to_bytes(&mut diagnostics);
+    let new = generate_full_asm(&[new_path.to_string()], options, &mut diagnostics);
+    let new_bytes = new.to_bytes(&mut diagnostics);
+    if diagnostics.has_errors() {
+        for diagnostic in diagnostics.items.iter() {
+            eprint!("{}", diagnostic.render(color, options.comment));
+        }
+        return false;
+    }
+
+    let differences = report::diff(&old, &old_bytes, &new, &new_bytes);
+    if differences.is_empty() {
+        println!("No differences");
+        true
+    } else {
+        print!("{}", differences);
+        false
+    }
+}
+
+/// Checks the checksum footer of each ROM, returning whether they all matched
+fn verify_checksums(paths: &[String], checksum: ChecksumKind) -> bool {
+    let mut ok = true;
+    for path in paths {
+        let result = std::fs::read(path)
+            .map_err(|e| format!("Unable to read {}: {}", path, e))
+            .and_then(|rom| checksum.verify(&rom));
+        match result {
+            Ok(()) => println!("{}: checksum ok", path),
+            Err(e) => {
+                eprintln!("{}: error: {}", path, e);
+                ok = false;
+            }
+        }
+    }
+    ok
+}
+
+/// Whether an argument is written like a number (decimal, `0x`/`#` hex or `%` binary)
+/// rather than a path
+fn looks_numeric(arg: &str) -> bool {
+    arg.starts_with("0x")
+        || arg.starts_with('#')
+        || arg.starts_with('%')
+        || (!arg.is_empty() && arg.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Splits the arguments into switches, flag values and positional arguments
+fn parse_args(args: Vec<String>) -> (Vec<String>, HashMap<String, String>, Vec<String>) {
+    let mut switches = Vec::new();
+    let mut values = HashMap::new();
+    let mut positional = Vec::new();
+    let mut args = args.into_iter();
+    while let Some(arg) = args.next() {
+        if let Some((name, value)) = arg
+            .split_once('=')
+            .filter(|(name, _)| VALUE_FLAGS.contains(name))
+        {
+            values.insert(name.to_string(), value.to_string());
+        } else if VALUE_FLAGS.contains(&arg.as_str()) {
+            match args.next() {
+                Some(value) => values.insert(arg, value),
+                None => usage_error(format!("{} needs a value", arg)),
+            };
+        } else if SWITCHES.contains(&arg.as_str()) {
+            switches.push(arg);
+        } else if arg.starts_with("--") {
+            // A misspelled flag would otherwise be ignored, and a source taken as the output
+            usage_error(format!("Unknown flag: {}", arg));
+        } else {
+            positional.push(arg);
+        }
+    }
+    (switches, values, positional)
+}
 
 fn main() {
-    let args: Vec<_> = env::args().collect();
-    if args.len() < 3 {
-        println!("Usage: cargo run 'path/to/asm' 'path/to/out' [offset]");
+    let (switches, values, mut paths) = parse_args(env::args().skip(1).collect());
+    let has = |name: &str| switches.iter().any(|s| s == name);
+    let check = has("--check");
+    let verbose = has("--verbose");
+    let quiet = has("--quiet");
+    let werror = has("--werror");
+    if quiet && verbose {
+        usage_error("--quiet and --verbose cannot be used together".to_string());
+    }
+    let watch_mode = has("--watch");
+    let fmt = has("--fmt");
+    let xref = has("--xref");
+    let map = has("--map");
+    let stats = has("--stats");
+    let diff = has("--diff");
+
+    if has("--list-instructions") {
+        print!("{}", report::instruction_list());
         return;
     }
 
-    let offset = if args.len() > 3 {
-        args[3].parse().unwrap()
+    let checksum_kind = |flag: &str| {
+        values.get(flag).map(|name| {
+            ChecksumKind::from_name(name)
+                .unwrap_or_else(|| usage_error(format!("Unknown checksum: {}", name)))
+        })
+    };
+    if let Some(checksum) = checksum_kind("--verify-checksum") {
+        if paths.is_empty() {
+            usage_error("--verify-checksum needs a ROM to check".to_string());
+        }
+        if !verify_checksums(&paths, checksum) {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+    let checksum = checksum_kind("--checksum");
+    let emit_dot = match values.get("--emit").map(|e| e.as_str()) {
+        Some("dot") => true,
+        Some(other) => usage_error(format!("Unknown --emit kind: {}", other)),
+        None => false,
+    };
+
+    // A target brings its usual quirks, which the quirk flags below can still override
+    let mut options = match values.get("--target") {
+        Some(name) => match Target::from_name(name) {
+            Some(target) => AssemblerOptions::for_target(target),
+            None => usage_error(format!("Unknown target: {}", name)),
+        },
+        None => AssemblerOptions::default(),
+    };
+    options.check_sprites = has("--check-sprites");
+    options.check_draws = has("--check-draws");
+    options.check_flags = has("--check-flags");
+    options.entry = values.get("--entry").cloned();
+    options.entry_jump = has("--entry-jump");
+    if options.entry_jump && options.entry.is_none() {
+        usage_error("--entry-jump needs --entry".to_string());
+    }
+    if let Some(name) = values.get("--shift-quirk") {
+        match ShiftQuirk::from_name(name) {
+            Some(quirk) => options.shift_quirk = Some(quirk),
+            None => usage_error(format!("Unknown shift quirk: {}", name)),
+        }
+    }
+    if let Some(comment) = values.get("--comment-char") {
+        let mut chars = comment.chars();
+        match (chars.next(), chars.next()) {
+            // Anything that can appear in code would cut lines short
+            (Some(c), None) if c.is_ascii_punctuation() && !matches!(c, '"' | '\'' | ',' | ':') => {
+                options.comment = c
+            }
+            _ => usage_error(format!("Invalid --comment-char: {}", comment)),
+        }
+    }
+    if let Some(name) = values.get("--offset-symbol") {
+        let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+            && name.chars().all(|c| c.is_alphanumeric() || c == '_');
+        if !valid {
+            usage_error(format!("Invalid --offset-symbol: {}", name));
+        }
+        options.offset_symbol = Some(name.clone());
+    }
+
+    if let Some(fill) = values.get("--fill") {
+        match Operand::parse_numeric_str(fill) {
+            Ok(fill @ 0..=0xFF) => options.fill = fill as u8,
+            _ => usage_error(format!("Invalid --fill byte: {}", fill)),
+        }
+    }
+    if let Some(size) = values.get("--max-rom-size") {
+        match Operand::parse_numeric_str(size) {
+            Ok(size) => options.max_rom_size = Some(size as usize),
+            Err(_) => usage_error(format!("Invalid --max-rom-size: {}", size)),
+        }
+    }
+
+    let format_name = values.get("--format");
+    let mut format = match format_name {
+        Some(name) => Format::from_name(name)
+            .unwrap_or_else(|| usage_error(format!("Unknown format: {}", name))),
+        None => Format::Raw,
+    };
+
+    let pad_to = values
+        .get("--pad-to")
+        .map(|size| match Operand::parse_numeric_str(size) {
+            Ok(size) => size as usize,
+            Err(_) => usage_error(format!("Invalid --pad-to size: {}", size)),
+        });
+
+    if has("--expand") || has("--print-tree") {
+        let flag = if has("--expand") {
+            "--expand"
+        } else {
+            "--print-tree"
+        };
+        if paths.is_empty() {
+            usage_error(format!("{} needs a source file", flag));
+        }
+        let mut diagnostics = Diagnostics::new();
+        if flag == "--expand" {
+            print!("{}", expand(&paths, &options, &mut diagnostics));
+        } else {
+            print!("{}", print_tree(&paths, &options, &mut diagnostics));
+        }
+        if werror {
+            diagnostics.promote_warnings();
+        }
+        if quiet {
+            diagnostics.drop_warnings();
+        }
+        for diagnostic in diagnostics.items.iter() {
+            eprint!("{}", diagnostic.render(false, options.comment));
+        }
+        if diagnostics.has_errors() {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+
+    if has("--repl") {
+        let stdin = io::stdin();
+        if let Err(e) = repl::run(stdin.lock(), io::stdout(), &options) {
+            eprintln!("error: {}", e);
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+
+    if has("--decode") {
+        if paths.is_empty() {
+            usage_error("--decode needs an opcode word to decode".to_string());
+        }
+        if !decode_words(&paths, options.target) {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+
+    // A trailing number is the load offset rather than a path
+    if let Some(last) = paths.last().filter(|p| paths.len() > 1 && looks_numeric(p)) {
+        match Operand::parse_numeric_str(last) {
+            Ok(offset) => options.offset = offset as usize,
+            Err(_) => usage_error(format!("Invalid offset: {}", last)),
+        }
+        paths.pop();
+    }
+
+    if has("--disassemble") {
+        if paths.len() != 1 {
+            usage_error("--disassemble needs exactly one ROM".to_string());
+        }
+        if !disassemble_rom(&paths[0], values.get("--symbol-map"), &options) {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+
+    let rename_to = values.get("--rename").map(|value| {
+        value
+            .split_once('=')
+            .unwrap_or_else(|| usage_error(format!("--rename expects OLD=NEW, found {}", value)))
+    });
+
+    // Check, format, rename and diff modes produce no binary, so every path is an input
+    let out_path = if check || fmt || diff || rename_to.is_some() {
+        None
     } else {
-        0x200
+        paths.pop()
+    };
+    if paths.is_empty() {
+        eprintln!("{}", USAGE);
+        process::exit(EXIT_USAGE);
+    }
+
+    // Without --format the output path's extension picks one, e.g. .hex for Intel HEX
+    if format_name.is_none() {
+        if let Some(detected) = out_path.as_deref().and_then(Format::from_extension) {
+            format = detected;
+        }
+    }
+
+    if let Some(bits) = values.get("--word-width") {
+        match (&mut format, WordWidth::from_bits(bits)) {
+            (Format::ReadMemH(width), Some(bits)) => *width = bits,
+            (Format::ReadMemH(_), None) => usage_error(format!("Unknown word width: {}", bits)),
+            _ => usage_error("--word-width only applies to --format readmemh".to_string()),
+        }
+    }
+    if let Some(attribute) = values.get("--c-attribute") {
+        match &mut format {
+            Format::CArray(slot) => *slot = Some(attribute.clone()),
+            _ => usage_error("--c-attribute only applies to --format carray".to_string()),
+        }
+    }
+
+    if fmt {
+        if !format_files(&paths, options.comment) {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+    if let Some((old, new)) = rename_to {
+        if !rename_symbol(&paths, &options, old, new) {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
+
+    let json_diagnostics = match values.get("--diagnostics").map(|d| d.as_str()) {
+        Some("json") => true,
+        Some("human") | None => false,
+        Some(other) => usage_error(format!("Unknown --diagnostics style: {}", other)),
     };
-    let mut full_asm = generate_full_asm(&args[1], offset);
+    let color =
+        !has("--no-color") && env::var_os("NO_COLOR").is_none() && io::stderr().is_terminal();
+    if diff {
+        if paths.len() != 2 {
+            usage_error("--diff needs exactly two programs to compare".to_string());
+        }
+        if !diff_programs(&paths[0], &paths[1], &options, color) {
+            process::exit(EXIT_ERROR);
+        }
+        return;
+    }
 
-    let bytes = full_asm.to_bytes();
+    let cli = Cli {
+        paths,
+        out_path,
+        options,
+        format,
+        pad_to,
+        checksum,
+        source_map: values.get("--source-map").cloned(),
+        symbol_map: values.get("--symbol-map").cloned(),
+        breakpoints: values.get("--breakpoints").cloned(),
+        verbose,
+        quiet,
+        werror,
+        verify: has("--verify"),
+        color,
+        json_diagnostics,
+        xref,
+        map,
+        stats,
+        registers: has("--registers"),
+        emit_dot,
+        coverage: has("--coverage"),
+        listing: has("--listing"),
+        pack_words: has("--pack-words"),
+    };
 
-    // Write to file
-    let mut file = std::fs::File::create(&args[2]).unwrap();
-    file.write_all(&bytes).unwrap();
+    if watch_mode {
+        if let Err(e) = watch(&cli) {
+            eprintln!("error: Unable to watch sources: {}", e);
+            process::exit(EXIT_ERROR);
+        }
+    } else if !assemble(&cli, &mut ParseCache::default()).1 {
+        process::exit(EXIT_ERROR);
+    }
 }
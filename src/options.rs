@@ -0,0 +1,120 @@
+/// How the two-operand `SHR Vx, Vy` / `SHL Vx, Vy` forms (8xy6 / 8xyE) are treated.
+/// The one-operand form always encodes y as 0, so `SHR V1` is 0x8106.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ShiftQuirk {
+    /// Original CHIP-8 shifts Vy into Vx, so Vy must be given
+    Legacy,
+    /// Later interpreters shift Vx in place and ignore Vy, so giving one is warned about
+    Modern,
+}
+impl ShiftQuirk {
+    pub fn from_name(name: &str) -> Option<ShiftQuirk> {
+        match name.to_lowercase().as_str() {
+            "legacy" => Some(ShiftQuirk::Legacy),
+            "modern" => Some(ShiftQuirk::Modern),
+            _ => None,
+        }
+    }
+}
+
+/// Instruction set a program is assembled for. Each target accepts everything the
+/// targets before it do.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Target {
+    Chip8,
+    SChip,
+    XoChip,
+}
+impl Target {
+    pub fn from_name(name: &str) -> Option<Target> {
+        match name.to_lowercase().as_str() {
+            "chip8" => Some(Target::Chip8),
+            "schip" => Some(Target::SChip),
+            "xochip" => Some(Target::XoChip),
+            _ => None,
+        }
+    }
+
+    /// Bytes of addressable memory, programs must end within it
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Target::Chip8 | Target::SChip => 0x1000,
+            Target::XoChip => 0x10000,
+        }
+    }
+
+    /// Shift behaviour of the usual interpreters for the target. Super-CHIP shifts Vx in place,
+    /// while the original CHIP-8 and Octo's XO-CHIP shift Vy into Vx.
+    pub fn default_shift_quirk(&self) -> ShiftQuirk {
+        match self {
+            Target::Chip8 | Target::XoChip => ShiftQuirk::Legacy,
+            Target::SChip => ShiftQuirk::Modern,
+        }
+    }
+
+    /// Name as given to `--target`
+    pub fn name(&self) -> &'static str {
+        match self {
+            Target::Chip8 => "chip8",
+            Target::SChip => "schip",
+            Target::XoChip => "xochip",
+        }
+    }
+}
+
+/// Settings that change how a program is assembled
+#[derive(Clone, Debug)]
+pub struct AssemblerOptions {
+    /// Address the program will be loaded at
+    pub offset: usize,
+    /// Shift behaviour to check SHR/SHL against, both forms are accepted when unset
+    pub shift_quirk: Option<ShiftQuirk>,
+    /// Instruction set to accept, newer instructions are rejected on older targets
+    pub target: Target,
+    /// Starts a comment running to the end of the line, unless it is inside quotes
+    pub comment: char,
+    /// Name of a label predefined at `offset`, so code can refer to its own load address
+    pub offset_symbol: Option<String>,
+    /// Warn when DRW draws more rows than the data LD I pointed it at holds
+    pub check_sprites: bool,
+    /// Warn when a DRW can run before anything has set I
+    pub check_draws: bool,
+    /// Warn when a value put in VF is overwritten by a carry or borrow before it is read
+    pub check_flags: bool,
+    /// Largest program in bytes, checked as well as the target's memory size
+    pub max_rom_size: Option<usize>,
+    /// Label the program starts running at, which must exist
+    pub entry: Option<String>,
+    /// Start the program with a `JP` to `entry` when code comes before it
+    pub entry_jump: bool,
+    /// Byte that fills the gaps left by `offset`, `align` and sections
+    pub fill: u8,
+}
+impl AssemblerOptions {
+    /// Options for `target` with its usual quirks, where the defaults accept every form
+    pub fn for_target(target: Target) -> Self {
+        Self {
+            target,
+            shift_quirk: Some(target.default_shift_quirk()),
+            ..Self::default()
+        }
+    }
+}
+impl Default for AssemblerOptions {
+    fn default() -> Self {
+        Self {
+            offset: 0x200,
+            shift_quirk: None,
+            target: Target::SChip,
+            comment: ';',
+            offset_symbol: None,
+            check_sprites: false,
+            check_draws: false,
+            check_flags: false,
+            max_rom_size: None,
+            entry: None,
+            entry_jump: false,
+            fill: 0,
+        }
+    }
+}
@@ -0,0 +1,340 @@
+use std::fmt::Write;
+
+/// Size of each value in word oriented formats
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WordWidth {
+    Byte,
+    /// Big endian pairs of bytes, one per CHIP-8 opcode
+    Word,
+}
+impl WordWidth {
+    pub fn from_bits(bits: &str) -> Option<WordWidth> {
+        match bits {
+            "8" => Some(WordWidth::Byte),
+            "16" => Some(WordWidth::Word),
+            _ => None,
+        }
+    }
+}
+
+/// Checksum appended after the program, big endian, to check ROM integrity
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumKind {
+    /// Sum of every byte, wrapping at 16 bits
+    Sum16,
+    /// CRC-16/CCITT-FALSE (polynomial 0x1021, initial value 0xFFFF)
+    Crc16,
+}
+impl ChecksumKind {
+    /// Bytes the checksum adds to the output
+    pub const SIZE: usize = 2;
+
+    pub fn from_name(name: &str) -> Option<ChecksumKind> {
+        match name.to_lowercase().as_str() {
+            "sum16" => Some(ChecksumKind::Sum16),
+            "crc16" => Some(ChecksumKind::Crc16),
+            _ => None,
+        }
+    }
+
+    pub fn compute(&self, bytes: &[u8]) -> u16 {
+        match self {
+            ChecksumKind::Sum16 => bytes
+                .iter()
+                .fold(0u16, |sum, b| sum.wrapping_add(*b as u16)),
+            ChecksumKind::Crc16 => bytes.iter().fold(0xFFFF, |mut crc: u16, b| {
+                crc ^= (*b as u16) << 8;
+                for _ in 0..8 {
+                    crc = if crc & 0x8000 != 0 {
+                        (crc << 1) ^ 0x1021
+                    } else {
+                        crc << 1
+                    };
+                }
+                crc
+            }),
+        }
+    }
+
+    pub fn append(&self, bytes: &mut Vec<u8>) {
+        let checksum = self.compute(bytes);
+        bytes.extend_from_slice(&checksum.to_be_bytes());
+    }
+
+    /// Checks the footer of a ROM written with `append`
+    pub fn verify(&self, rom: &[u8]) -> Result<(), String> {
+        if rom.len() < ChecksumKind::SIZE {
+            return Err(format!(
+                "ROM is only {} bytes, too short for a checksum",
+                rom.len()
+            ));
+        }
+        let (program, footer) = rom.split_at(rom.len() - ChecksumKind::SIZE);
+        let expected = u16::from_be_bytes([footer[0], footer[1]]);
+        let actual = self.compute(program);
+        if expected == actual {
+            Ok(())
+        } else {
+            Err(format!(
+                "Checksum mismatch, footer is {:#06x} but the program sums to {:#06x}",
+                expected, actual
+            ))
+        }
+    }
+}
+
+/// How the assembled bytes are written out
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// The bytes as they are, ready to load into an emulator
+    Raw,
+    /// Xilinx memory initialization file
+    Coe,
+    /// Altera/Intel memory initialization file
+    Mif,
+    /// Hex values one per line for Verilog's `$readmemh`
+    ReadMemH(WordWidth),
+    /// Intel HEX records addressed from the load offset
+    IntelHex,
+    /// Motorola S-records addressed from the load offset
+    Srec,
+    /// C source declaring the bytes as an array, with an attribute such as `PROGMEM` after
+    /// its name when one is given
+    CArray(Option<String>),
+    /// Addresses, hex bytes and ASCII side by side for reading
+    HexDump,
+    /// A Python bytes literal assigned to `rom`
+    Python,
+    /// Standard base64 on one line, for embedding in JSON or web pages
+    Base64,
+}
+impl Format {
+    pub fn from_name(name: &str) -> Option<Format> {
+        match name.to_lowercase().as_str() {
+            "raw" | "bin" => Some(Format::Raw),
+            "coe" => Some(Format::Coe),
+            "mif" => Some(Format::Mif),
+            "readmemh" => Some(Format::ReadMemH(WordWidth::Byte)),
+            "ihex" => Some(Format::IntelHex),
+            "srec" => Some(Format::Srec),
+            "carray" => Some(Format::CArray(None)),
+            "hexdump" => Some(Format::HexDump),
+            "python" => Some(Format::Python),
+            "base64" => Some(Format::Base64),
+            _ => None,
+        }
+    }
+
+    /// Format implied by an output path's extension, used when none is given
+    pub fn from_extension(path: &str) -> Option<Format> {
+        let (_, extension) = path.rsplit_once('.')?;
+        match extension.to_lowercase().as_str() {
+            "hex" | "ihex" => Some(Format::IntelHex),
+            "srec" | "s19" => Some(Format::Srec),
+            "h" | "c" => Some(Format::CArray(None)),
+            "coe" => Some(Format::Coe),
+            "mif" => Some(Format::Mif),
+            "mem" => Some(Format::ReadMemH(WordWidth::Byte)),
+            "py" => Some(Format::Python),
+            "b64" => Some(Format::Base64),
+            _ => None,
+        }
+    }
+
+    /// Writes the bytes of a program loaded at `offset`
+    pub fn serialize(&self, bytes: &[u8], offset: usize) -> Vec<u8> {
+        match self {
+            Format::Raw => bytes.to_vec(),
+            Format::Coe => to_coe(bytes).into_bytes(),
+            Format::Mif => to_mif(bytes).into_bytes(),
+            Format::ReadMemH(width) => to_readmemh(bytes, *width).into_bytes(),
+            Format::IntelHex => to_intel_hex(bytes, offset).into_bytes(),
+            Format::Srec => to_srec(bytes, offset).into_bytes(),
+            Format::CArray(attribute) => {
+                to_c_array(bytes, offset, attribute.as_deref()).into_bytes()
+            }
+            Format::HexDump => to_hexdump(bytes, offset).into_bytes(),
+            Format::Python => to_python_bytes(bytes).into_bytes(),
+            Format::Base64 => to_base64(bytes).into_bytes(),
+        }
+    }
+}
+
+/// Xilinx COE file with one hex byte per line
+pub fn to_coe(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    writeln!(out, "; CHIP-8 program, {} bytes", bytes.len()).unwrap();
+    writeln!(out, "memory_initialization_radix=16;").unwrap();
+    writeln!(out, "memory_initialization_vector=").unwrap();
+    let values: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+    writeln!(out, "{};", values.join(",\n")).unwrap();
+    out
+}
+
+/// Altera MIF file with one hex byte per address, addresses start at 0 rather than the load offset
+pub fn to_mif(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    writeln!(out, "-- CHIP-8 program, {} bytes", bytes.len()).unwrap();
+    writeln!(out, "DEPTH = {};", bytes.len()).unwrap();
+    writeln!(out, "WIDTH = 8;").unwrap();
+    writeln!(out, "ADDRESS_RADIX = HEX;").unwrap();
+    writeln!(out, "DATA_RADIX = HEX;").unwrap();
+    writeln!(out, "CONTENT").unwrap();
+    writeln!(out, "BEGIN").unwrap();
+    for (address, byte) in bytes.iter().enumerate() {
+        writeln!(out, "{:04X} : {:02X};", address, byte).unwrap();
+    }
+    writeln!(out, "END;").unwrap();
+    out
+}
+
+/// One hex value per line with no addresses, as read by Verilog's `$readmemh`. With 16-bit words
+/// an odd trailing byte is padded with zero.
+pub fn to_readmemh(bytes: &[u8], width: WordWidth) -> String {
+    let mut out = String::new();
+    match width {
+        WordWidth::Byte => {
+            for byte in bytes {
+                writeln!(out, "{:02X}", byte).unwrap();
+            }
+        }
+        WordWidth::Word => {
+            for pair in bytes.chunks(2) {
+                let low = pair.get(1).copied().unwrap_or(0);
+                writeln!(out, "{:02X}{:02X}", pair[0], low).unwrap();
+            }
+        }
+    }
+    out
+}
+
+/// Intel HEX with 16 byte data records starting at `offset`, followed by the end of file record
+pub fn to_intel_hex(bytes: &[u8], offset: usize) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let address = (offset + i * 16) as u16;
+        let mut record = vec![chunk.len() as u8, (address >> 8) as u8, address as u8, 0x00];
+        record.extend_from_slice(chunk);
+        let checksum = record
+            .iter()
+            .fold(0u8, |sum, b| sum.wrapping_add(*b))
+            .wrapping_neg();
+        out.push(':');
+        for byte in record {
+            write!(out, "{:02X}", byte).unwrap();
+        }
+        writeln!(out, "{:02X}", checksum).unwrap();
+    }
+    writeln!(out, ":00000001FF").unwrap();
+    out
+}
+
+/// Motorola S-records: an empty S0 header, S1 data records of 16 bytes starting at `offset` and
+/// an S9 record giving `offset` as the start address
+pub fn to_srec(bytes: &[u8], offset: usize) -> String {
+    let mut out = String::new();
+    let mut record = |kind: char, address: u16, data: &[u8]| {
+        // The count covers the address, data and checksum bytes
+        let mut fields = vec![data.len() as u8 + 3, (address >> 8) as u8, address as u8];
+        fields.extend_from_slice(data);
+        let checksum = !fields.iter().fold(0u8, |sum, b| sum.wrapping_add(*b));
+        write!(out, "S{}", kind).unwrap();
+        for byte in fields {
+            write!(out, "{:02X}", byte).unwrap();
+        }
+        writeln!(out, "{:02X}", checksum).unwrap();
+    };
+    record('0', 0, &[]);
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        record('1', (offset + i * 16) as u16, chunk);
+    }
+    record('9', offset as u16, &[]);
+    out
+}
+
+/// C source declaring the bytes as `const unsigned char rom[]`, followed by `attribute` when
+/// given, e.g. `PROGMEM` to keep the array in flash on AVR
+pub fn to_c_array(bytes: &[u8], offset: usize, attribute: Option<&str>) -> String {
+    let mut out = String::new();
+    writeln!(
+        out,
+        "/* CHIP-8 program, {} bytes, loaded at {:#06x} */",
+        bytes.len(),
+        offset
+    )
+    .unwrap();
+    let attribute = attribute.map_or(String::new(), |a| format!(" {}", a));
+    writeln!(
+        out,
+        "const unsigned char rom[{}]{} = {{",
+        bytes.len(),
+        attribute
+    )
+    .unwrap();
+    for chunk in bytes.chunks(12) {
+        let values: Vec<String> = chunk.iter().map(|b| format!("0x{:02X}", b)).collect();
+        writeln!(out, "    {},", values.join(", ")).unwrap();
+    }
+    writeln!(out, "}};").unwrap();
+    out
+}
+
+/// Python source assigning the bytes to `rom` as a bytes literal. Every byte is written as a
+/// `\xNN` escape, so no value needs special casing.
+pub fn to_python_bytes(bytes: &[u8]) -> String {
+    let mut out = String::from("rom = b\"");
+    for b in bytes {
+        write!(out, "\\x{:02x}", b).unwrap();
+    }
+    out.push_str("\"\n");
+    out
+}
+
+/// Standard base64 (RFC 4648) with `=` padding, on a single line
+pub fn to_base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4 + 1);
+    for chunk in bytes.chunks(3) {
+        let group = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |group, (i, b)| group | (*b as u32) << (16 - 8 * i));
+        // A chunk of n bytes fills n + 1 characters, the rest is padding
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(group >> (18 - 6 * i) & 0x3F) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out.push('\n');
+    out
+}
+
+/// 16 bytes per line with the address of the first and the printable ones as ASCII
+pub fn to_hexdump(bytes: &[u8], offset: usize) -> String {
+    let mut out = String::new();
+    for (i, chunk) in bytes.chunks(16).enumerate() {
+        let hex: Vec<String> = chunk.iter().map(|b| format!("{:02x}", b)).collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|b| {
+                if b.is_ascii_graphic() || *b == b' ' {
+                    *b as char
+                } else {
+                    '.'
+                }
+            })
+            .collect();
+        writeln!(
+            out,
+            "{:04x}: {:<47}  |{}|",
+            offset + i * 16,
+            hex.join(" "),
+            ascii
+        )
+        .unwrap();
+    }
+    out
+}
@@ -0,0 +1,99 @@
+use crate::asm::{is_reserved_name, Assembly};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+
+/// Whether `c` can be part of a label or define name
+fn is_name_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+/// Replaces every whole-word `old` in the code of `line` with `new`, leaving the comment and
+/// quoted strings alone
+fn rename_in_line(line: &str, old: &str, new: &str, comment: char) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut quote: Option<char> = None;
+    let mut rest = line;
+    while let Some(c) = rest.chars().next() {
+        match (quote, c) {
+            (None, '"' | '\'') => quote = Some(c),
+            (Some(q), _) if q == c => quote = None,
+            // The comment runs to the end of the line
+            (None, _) if c == comment => {
+                out.push_str(rest);
+                return out;
+            }
+            (None, _) if is_name_char(c) => {
+                let end = rest.find(|c| !is_name_char(c)).unwrap_or(rest.len());
+                out.push_str(if &rest[..end] == old {
+                    new
+                } else {
+                    &rest[..end]
+                });
+                rest = &rest[end..];
+                continue;
+            }
+            _ => {}
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// Renames the label or define `old` to `new` at its definition and every use, returning the
+/// rewritten source of each file that changed by path
+pub fn rename(
+    assembly: &Assembly,
+    old: &str,
+    new: &str,
+) -> Result<BTreeMap<String, String>, String> {
+    let symbol = assembly
+        .symbols
+        .get(old)
+        .ok_or_else(|| format!("No label or define named '{}'", old))?;
+    let valid =
+        new.starts_with(|c: char| c.is_alphabetic() || c == '_') && new.chars().all(is_name_char);
+    if !valid {
+        return Err(format!("'{}' is not a valid name", new));
+    }
+    if is_reserved_name(new) {
+        return Err(format!(
+            "'{}' is reserved and cannot be used as a name",
+            new
+        ));
+    }
+    if let Some(existing) = assembly.symbols.get(new) {
+        return Err(format!(
+            "'{}' is already defined at {}",
+            new, existing.defined_at
+        ));
+    }
+
+    // Lines to rewrite in each file
+    let mut lines: BTreeMap<&str, BTreeSet<usize>> = BTreeMap::new();
+    for span in std::iter::once(&symbol.defined_at).chain(symbol.references.iter()) {
+        lines.entry(&span.file).or_default().insert(span.line);
+    }
+
+    let mut rewritten = BTreeMap::new();
+    for (file, numbers) in lines {
+        let source =
+            fs::read_to_string(file).map_err(|e| format!("Unable to read {}: {}", file, e))?;
+        let mut out: String = source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| match numbers.contains(&(i + 1)) {
+                true => rename_in_line(line, old, new, assembly.options.comment),
+                false => line.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("\n");
+        if source.ends_with('\n') {
+            out.push('\n');
+        }
+        if out != source {
+            rewritten.insert(file.to_string(), out);
+        }
+    }
+    Ok(rewritten)
+}
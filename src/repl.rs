@@ -0,0 +1,104 @@
+use crate::asm::{
+    extract_label, format_line, parse_entry, parse_label, AsmEnum, Assembly, SourceSpan,
+};
+use crate::diagnostics::{Diagnostics, Severity};
+use crate::options::AssemblerOptions;
+use std::io::{self, BufRead, Write};
+
+/// File name given to lines typed into the REPL
+const REPL_FILE: &str = "<repl>";
+
+/// Parses every line entered so far into entries
+fn parse_lines(lines: &[String]) -> Result<Vec<(AsmEnum, SourceSpan)>, String> {
+    let mut entries = Vec::new();
+    for (i, line) in lines.iter().enumerate() {
+        let span = SourceSpan {
+            file: REPL_FILE.to_string(),
+            line: i + 1,
+            text: line.as_str().into(),
+        };
+        let mut line = line.clone();
+        loop {
+            match extract_label(line.clone()) {
+                Some((label, rem_line)) => {
+                    entries.push((AsmEnum::Label(parse_label(label)?), span.clone()));
+                    match rem_line {
+                        Some(rem_line) => line = rem_line,
+                        None => break,
+                    }
+                }
+                None => {
+                    entries.push((parse_entry(line)?, span.clone()));
+                    break;
+                }
+            }
+        }
+    }
+    Ok(entries)
+}
+
+/// Assembles every line entered so far, returning the address and bytes of the last one
+fn assemble_lines(
+    lines: &[String],
+    options: &AssemblerOptions,
+) -> Result<(usize, Vec<u8>), Vec<String>> {
+    let entries = parse_lines(lines).map_err(|e| vec![e])?;
+    let assembly = Assembly::new(entries, Vec::new(), options.clone());
+    let mut diagnostics = Diagnostics::new();
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    if diagnostics.has_errors() {
+        return Err(diagnostics
+            .items
+            .iter()
+            .filter(|d| d.severity == Severity::Error)
+            .map(|d| d.message.clone())
+            .collect());
+    }
+
+    let address = assembly
+        .instructions
+        .iter()
+        .find(|(_, _, span)| span.line == lines.len())
+        .map_or(options.offset + bytes.len(), |(_, offset, _)| *offset);
+    Ok((address, bytes[address - options.offset..].to_vec()))
+}
+
+/// Reads one line at a time, printing the address, hex and binary of what it assembles to.
+/// Defines and labels carry over to later lines, and a line with errors is forgotten.
+pub fn run(
+    input: impl BufRead,
+    mut output: impl Write,
+    options: &AssemblerOptions,
+) -> io::Result<()> {
+    let mut lines: Vec<String> = Vec::new();
+    write!(output, "> ")?;
+    output.flush()?;
+    for line in input.lines() {
+        if let Some(line) = format_line(line?, options.comment) {
+            lines.push(line);
+            match assemble_lines(&lines, options) {
+                Ok((_, bytes)) if bytes.is_empty() => {}
+                Ok((address, bytes)) => {
+                    let hex: Vec<String> = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+                    let binary: Vec<String> = bytes.iter().map(|b| format!("{:08b}", b)).collect();
+                    writeln!(
+                        output,
+                        "{:#06x}: {}  {}",
+                        address,
+                        hex.join(" "),
+                        binary.join(" ")
+                    )?;
+                }
+                Err(errors) => {
+                    lines.pop();
+                    for error in errors {
+                        writeln!(output, "error: {}", error)?;
+                    }
+                }
+            }
+        }
+        write!(output, "> ")?;
+        output.flush()?;
+    }
+    writeln!(output)
+}
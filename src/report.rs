@@ -0,0 +1,604 @@
+use crate::asm::{split_comment, AsmEnum, Assembly, Operand, SymbolKind};
+use crate::instructions::{decode_info, supported_instructions, InstructionInfo};
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fs;
+
+/// Renders rows as left aligned columns separated by two spaces
+fn table(header: &[&str], rows: Vec<Vec<String>>) -> String {
+    let mut widths: Vec<usize> = header.iter().map(|h| h.len()).collect();
+    for row in rows.iter() {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let header = header.iter().map(|h| h.to_string()).collect();
+    let mut out = String::new();
+    for row in std::iter::once(header).chain(rows) {
+        let cells: Vec<String> = row
+            .iter()
+            .zip(widths.iter())
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        out.push_str(cells.join("  ").trim_end());
+        out.push('\n');
+    }
+    out
+}
+
+/// Cross-reference of every label and define, sorted by name, with where it was
+/// declared and every line that uses it
+pub fn xref(assembly: &Assembly) -> String {
+    let rows = assembly
+        .symbols
+        .iter()
+        .map(|(name, symbol)| {
+            let references = if symbol.references.is_empty() {
+                "-".to_string()
+            } else {
+                symbol
+                    .references
+                    .iter()
+                    .map(|r| r.to_string())
+                    .collect::<Vec<String>>()
+                    .join(", ")
+            };
+            vec![
+                name.clone(),
+                symbol.kind.to_string(),
+                symbol.defined_at.to_string(),
+                references,
+            ]
+        })
+        .collect();
+    table(&["Symbol", "Kind", "Defined", "References"], rows)
+}
+
+/// Every supported mnemonic and operand form with its encoding and the target it needs
+pub fn instruction_list() -> String {
+    let rows = supported_instructions()
+        .iter()
+        .map(|i| {
+            vec![
+                i.mnemonic.to_string(),
+                i.form.to_string(),
+                i.opcode.to_string(),
+                i.target.name().to_string(),
+            ]
+        })
+        .collect();
+    table(&["Mnemonic", "Operands", "Opcode", "Target"], rows)
+}
+
+/// How many of the program's instructions encode each opcode the target supports, in the order
+/// of `--list-instructions`. Forms sharing an encoding, such as `LD Vx, Vy` and `MOV`, are one
+/// opcode.
+pub fn opcode_usage(assembly: &Assembly, bytes: &[u8]) -> Vec<(&'static InstructionInfo, usize)> {
+    let target = assembly.options.target;
+    let mut uses: HashMap<&str, usize> = HashMap::new();
+    for entry in assembly.iter_resolved(bytes) {
+        if !matches!(entry.kind, AsmEnum::Instruction(_)) {
+            continue;
+        }
+        if let Some(info) = decode_info(entry.bytes, target) {
+            *uses.entry(info.opcode).or_default() += 1;
+        }
+    }
+    let mut seen = BTreeSet::new();
+    supported_instructions()
+        .iter()
+        .filter(|i| i.target <= target && seen.insert(i.opcode))
+        .map(|i| (i, uses.get(i.opcode).copied().unwrap_or(0)))
+        .collect()
+}
+
+/// Opcode coverage table listing every opcode the target supports and how often the program
+/// uses it, for checking a test ROM exercises the whole instruction set
+pub fn opcode_coverage(assembly: &Assembly, bytes: &[u8]) -> String {
+    let usage = opcode_usage(assembly, bytes);
+    let covered = usage.iter().filter(|(_, n)| *n > 0).count();
+    let mut out = format!("Covered {} of {} opcodes\n\n", covered, usage.len());
+    let rows = usage
+        .iter()
+        .map(|(info, n)| {
+            let uses = if *n == 0 {
+                "-".to_string()
+            } else {
+                n.to_string()
+            };
+            vec![info.opcode.to_string(), info.to_string(), uses]
+        })
+        .collect();
+    out.push_str(&table(&["Opcode", "Instruction", "Uses"], rows));
+    out
+}
+
+/// Memory map with one row per contiguous region of code, data or reserved padding. A label
+/// starts a new region so each routine and table gets its own row.
+pub fn memory_map(assembly: &Assembly) -> String {
+    // (start, size, kind, labels)
+    let mut regions: Vec<(usize, usize, &str, Vec<&str>)> = Vec::new();
+    let mut labels: Vec<&str> = Vec::new();
+    for (index, (entry, offset, _)) in assembly.instructions.iter().enumerate() {
+        let kind = match entry {
+            AsmEnum::Label(l) => {
+                labels.push(&l.name);
+                continue;
+            }
+            AsmEnum::Define(_) => continue,
+            AsmEnum::Instruction(_) => "code",
+            // The padding before a section, whose name goes with what follows
+            AsmEnum::Directive(dir) if dir.mnemonic.eq_ignore_ascii_case("section") => {
+                let size = assembly.entry_size(index);
+                if size > 0 {
+                    regions.push((*offset, size, "reserved", std::mem::take(&mut labels)));
+                }
+                labels.push(&dir.args[0]);
+                continue;
+            }
+            AsmEnum::Directive(dir) => match dir.mnemonic.to_lowercase().as_str() {
+                "offset" | "align" => "reserved",
+                _ => "data",
+            },
+        };
+        let size = assembly.entry_size(index);
+        if size == 0 {
+            continue;
+        }
+        match regions.last_mut() {
+            Some((start, region_size, region_kind, _))
+                if labels.is_empty()
+                    && *region_kind == kind
+                    && *start + *region_size == *offset =>
+            {
+                *region_size += size
+            }
+            _ => regions.push((*offset, size, kind, std::mem::take(&mut labels))),
+        }
+    }
+
+    let rows = regions
+        .into_iter()
+        .map(|(start, size, kind, labels)| {
+            vec![
+                format!("{:#06x}-{:#06x}", start, start + size - 1),
+                size.to_string(),
+                kind.to_string(),
+                labels.join(", "),
+            ]
+        })
+        .collect();
+    table(&["Range", "Size", "Kind", "Labels"], rows)
+}
+
+/// Which V registers each routine reads and writes, where a routine runs from one label to
+/// the next. Useful for finding free registers and accidental clobbers.
+pub fn register_usage(assembly: &Assembly) -> String {
+    // (name, reads, writes) of every routine that has instructions
+    let mut routines: Vec<(String, BTreeSet<u16>, BTreeSet<u16>)> = Vec::new();
+    let mut label: Option<&str> = None;
+    for (entry, offset, _) in assembly.instructions.iter() {
+        match entry {
+            AsmEnum::Label(l) => {
+                label.get_or_insert(&l.name);
+            }
+            AsmEnum::Instruction(inst) => {
+                if label.is_some() || routines.is_empty() {
+                    let name = label
+                        .take()
+                        .map_or(format!("{:#06x}", offset), str::to_string);
+                    routines.push((name, BTreeSet::new(), BTreeSet::new()));
+                }
+                let (reads, writes) = inst.register_roles();
+                let (_, routine_reads, routine_writes) = routines.last_mut().unwrap();
+                routine_reads.extend(reads);
+                routine_writes.extend(writes);
+            }
+            _ => {}
+        }
+    }
+
+    let list = |registers: BTreeSet<u16>| {
+        if registers.is_empty() {
+            return "-".to_string();
+        }
+        registers
+            .iter()
+            .map(|r| format!("V{:X}", r))
+            .collect::<Vec<String>>()
+            .join(", ")
+    };
+    let rows = routines
+        .into_iter()
+        .map(|(name, reads, writes)| vec![name, list(reads), list(writes)])
+        .collect();
+    table(&["Routine", "Reads", "Writes"], rows)
+}
+
+/// Summary of the program: sizes, instruction counts by mnemonic, and symbol counts
+pub fn stats(assembly: &Assembly) -> String {
+    let mut code_bytes = 0;
+    let mut data_bytes = 0;
+    let mut reserved_bytes = 0;
+    let mut histogram: BTreeMap<String, usize> = BTreeMap::new();
+    for (index, (entry, ..)) in assembly.instructions.iter().enumerate() {
+        let size = assembly.entry_size(index);
+        match entry {
+            AsmEnum::Instruction(inst) => {
+                code_bytes += size;
+                *histogram.entry(inst.mnemonic.to_uppercase()).or_default() += 1;
+            }
+            AsmEnum::Directive(dir) => match dir.mnemonic.to_lowercase().as_str() {
+                "offset" | "align" | "section" => reserved_bytes += size,
+                _ => data_bytes += size,
+            },
+            _ => {}
+        }
+    }
+    let count = |kind: SymbolKind| assembly.symbols.values().filter(|s| s.kind == kind).count();
+
+    let mut out = String::new();
+    out.push_str(&format!(
+        "Total bytes: {}\n",
+        code_bytes + data_bytes + reserved_bytes
+    ));
+    out.push_str(&format!("Code bytes: {}\n", code_bytes));
+    out.push_str(&format!("Data bytes: {}\n", data_bytes));
+    out.push_str(&format!("Reserved bytes: {}\n", reserved_bytes));
+    out.push_str(&format!(
+        "Instructions: {}\n",
+        histogram.values().sum::<usize>()
+    ));
+    out.push_str(&format!("Labels: {}\n", count(SymbolKind::Label)));
+    out.push_str(&format!("Defines: {}\n", count(SymbolKind::Define)));
+    out.push('\n');
+
+    // Most used first, ties by name
+    let mut rows: Vec<(String, usize)> = histogram.into_iter().collect();
+    rows.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    let rows = rows
+        .into_iter()
+        .map(|(mnemonic, n)| vec![mnemonic, n.to_string()])
+        .collect();
+    out.push_str(&table(&["Mnemonic", "Count"], rows));
+    out
+}
+
+/// GraphViz DOT control flow graph. Blocks start at labels and jump targets and end after any
+/// jump, call, skip or return. Edges follow JP and CALL targets, skips and fall-through.
+pub fn control_flow_dot(assembly: &Assembly) -> String {
+    let mut names: HashMap<usize, &str> = HashMap::new();
+    for (entry, offset, _) in assembly.instructions.iter() {
+        if let AsmEnum::Label(l) = entry {
+            names.entry(*offset).or_insert(&l.name);
+        }
+    }
+    // (address, size, mnemonic, text) of every instruction, split into blocks
+    let blocks: BTreeMap<usize, Vec<(usize, usize, String, String)>> = assembly
+        .basic_blocks()
+        .into_iter()
+        .map(|(start, indices)| {
+            let insts = indices
+                .into_iter()
+                .filter_map(|index| match &assembly.instructions[index] {
+                    (AsmEnum::Instruction(inst), offset, _) => {
+                        let mnemonic = inst.mnemonic.to_uppercase();
+                        let args: Vec<String> = inst.args.iter().map(|a| a.repr.clone()).collect();
+                        let text = format!("{} {}", mnemonic, args.join(", "));
+                        Some((*offset, assembly.entry_size(index), mnemonic, text))
+                    }
+                    _ => None,
+                })
+                .collect();
+            (start, insts)
+        })
+        .collect();
+    let code: Vec<&(usize, usize, String, String)> = blocks.values().flatten().collect();
+    // Jump or call target of each instruction that has one
+    let targets: HashMap<usize, usize> = assembly
+        .instructions
+        .iter()
+        .filter_map(|(entry, offset, _)| match entry {
+            AsmEnum::Instruction(inst) => Some((*offset, Assembly::jump_target(inst)?)),
+            _ => None,
+        })
+        .collect();
+    let node = |address: usize| format!("b{:04x}", address);
+
+    let mut out = String::from("digraph cfg {\n    node [shape=box, fontname=monospace];\n");
+    for (start, insts) in blocks.iter() {
+        let title = match names.get(start) {
+            Some(name) => format!("{} ({:#06x})", name, start),
+            None => format!("{:#06x}", start),
+        };
+        let lines: String = insts.iter().map(|i| format!("{}\\l", i.3.trim())).collect();
+        out.push_str(&format!(
+            "    {} [label=\"{}\\l{}\"];\n",
+            node(*start),
+            title,
+            lines
+        ));
+    }
+
+    let starts: Vec<usize> = blocks.keys().copied().collect();
+    for (i, (start, insts)) in blocks.iter().enumerate() {
+        let (address, size, mnemonic, _) = insts.last().unwrap();
+        let next = starts.get(i + 1).copied();
+        let mut edge = |to: Option<usize>, attributes: &str| {
+            if let Some(to) = to.filter(|to| blocks.contains_key(to)) {
+                out.push_str(&format!(
+                    "    {} -> {}{};\n",
+                    node(*start),
+                    node(to),
+                    attributes
+                ));
+            }
+        };
+        let target = targets.get(address).copied();
+        match mnemonic.as_str() {
+            "JP" => edge(target, ""),
+            "CALL" => {
+                edge(target, " [style=dashed, label=\"call\"]");
+                edge(next, "");
+            }
+            "RET" | "EXIT" => {}
+            "SE" | "SNE" | "SKP" | "SKNP" => {
+                edge(next, "");
+                // The skipped instruction is the first of the next block
+                let skipped = code.iter().find(|c| c.0 == address + size);
+                edge(skipped.map(|c| c.0 + c.1), " [label=\"skip\"]");
+            }
+            _ => edge(next, ""),
+        }
+    }
+    out.push_str("}\n");
+    out
+}
+
+/// Byte level differences between two assembled programs, one line for each run of differing
+/// bytes with its address and the source lines on both sides. Empty when they are identical.
+pub fn diff(old: &Assembly, old_bytes: &[u8], new: &Assembly, new_bytes: &[u8]) -> String {
+    let hex = |bytes: &[u8], range: std::ops::Range<usize>| {
+        range
+            .map(|i| {
+                bytes
+                    .get(i)
+                    .map_or("--".to_string(), |b| format!("{:02x}", b))
+            })
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+    let source = |assembly: &Assembly, address: usize| {
+        assembly
+            .span_at(address)
+            .map_or("-".to_string(), |span| span.to_string())
+    };
+
+    let mut out = String::new();
+    let len = old_bytes.len().max(new_bytes.len());
+    let mut i = 0;
+    while i < len {
+        if old_bytes.get(i) == new_bytes.get(i) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < len && old_bytes.get(i) != new_bytes.get(i) {
+            i += 1;
+        }
+        let address = new.options.offset + start;
+        out.push_str(&format!(
+            "{:#06x}: {} -> {}  ({} -> {})\n",
+            address,
+            hex(old_bytes, start..i),
+            hex(new_bytes, start..i),
+            source(old, old.options.offset + start),
+            source(new, address)
+        ));
+    }
+    out
+}
+
+/// Quotes a CSV field when it holds a comma, quote or newline
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Assembly listing with the address and bytes of every entry next to its source, resolved
+/// as it was assembled, and where it came from. Data runs 8 bytes to a line. With
+/// `pack_words` data bytes are shown in pairs as 16-bit words, which is easier to scan.
+pub fn listing(assembly: &Assembly, bytes: &[u8], pack_words: bool) -> String {
+    // Hex lines up unless an instruction is longer than this
+    const WIDTH: usize = 24;
+    let comment = assembly.options.comment;
+    let mut out = String::new();
+    for entry in assembly.iter_resolved(bytes) {
+        let source = entry.kind.to_source();
+        match entry.kind {
+            AsmEnum::Define(_) => continue,
+            AsmEnum::Instruction(_) => {
+                let hex: String = entry.bytes.iter().map(|b| format!("{:02X}", b)).collect();
+                out.push_str(&format!(
+                    "{:#06x}  {:<WIDTH$}{} {} {}\n",
+                    entry.offset, hex, source, comment, entry.source_span
+                ));
+            }
+            _ if entry.bytes.is_empty() => {
+                out.push_str(&format!("{:6}  {:<WIDTH$}{}\n", "", "", source));
+            }
+            _ => {
+                for (row, chunk) in entry.bytes.chunks(8).enumerate() {
+                    let hex: Vec<String> = if pack_words {
+                        chunk
+                            .chunks(2)
+                            .map(|pair| pair.iter().map(|b| format!("{:02X}", b)).collect())
+                            .collect()
+                    } else {
+                        chunk.iter().map(|b| format!("{:02X}", b)).collect()
+                    };
+                    let address = entry.offset + row * 8;
+                    let hex = hex.join(" ");
+                    if row == 0 {
+                        out.push_str(&format!(
+                            "{:#06x}  {:<WIDTH$}{} {} {}\n",
+                            address, hex, source, comment, entry.source_span
+                        ));
+                    } else {
+                        out.push_str(&format!("{:#06x}  {}\n", address, hex));
+                    }
+                }
+            }
+        }
+    }
+    out
+}
+
+/// CSV source map with the address, size, file and line of every entry that emits bytes,
+/// so a debugger can find the source line for a PC
+pub fn source_map(assembly: &Assembly) -> String {
+    let mut out = String::from("address,size,file,line\n");
+    for (index, (_, offset, span)) in assembly.instructions.iter().enumerate() {
+        let size = assembly.entry_size(index);
+        if size == 0 {
+            continue;
+        }
+        out.push_str(&format!(
+            "{:#06x},{},{},{}\n",
+            offset,
+            size,
+            csv_field(&span.file),
+            span.line
+        ));
+    }
+    out
+}
+
+/// CSV symbol map with the address of every label, which `--disassemble` reads back to name
+/// jump and call targets. The flags field is `entry` for the entry label and empty otherwise.
+pub fn symbol_map(assembly: &Assembly) -> String {
+    let mut out = String::from("address,name,flags\n");
+    for (entry, offset, _) in assembly.instructions.iter() {
+        if let Some(name) = entry.label_name() {
+            let flags = if assembly.options.entry.as_deref() == Some(name) {
+                "entry"
+            } else {
+                ""
+            };
+            out.push_str(&format!("{:#06x},{},{}\n", offset, csv_field(name), flags));
+        }
+    }
+    out
+}
+
+/// CSV of `name,address` for every label that code follows, for emulators to import as
+/// named breakpoints. Labels on data are left out.
+pub fn breakpoints(assembly: &Assembly) -> String {
+    let mut out = String::new();
+    let mut pending: Vec<(&str, usize)> = Vec::new();
+    for (entry, offset, _) in assembly.instructions.iter() {
+        if let Some(name) = entry.label_name() {
+            pending.push((name, *offset));
+            continue;
+        }
+        match entry {
+            AsmEnum::Define(_) => {}
+            AsmEnum::Instruction(_) => {
+                for (name, address) in pending.drain(..) {
+                    out.push_str(&format!("{},{:#06x}\n", csv_field(name), address));
+                }
+            }
+            _ => pending.clear(),
+        }
+    }
+    out
+}
+
+/// Lines holding a `; sprite [name]` comment in each source file
+fn sprite_markers(files: &[String], comment: char) -> HashMap<&str, Vec<(usize, String)>> {
+    let mut markers = HashMap::new();
+    for file in files {
+        let source = fs::read_to_string(file).unwrap_or_default();
+        let lines = source
+            .lines()
+            .enumerate()
+            .filter_map(|(i, line)| {
+                let comment = split_comment(line, comment).1?.trim();
+                let name = comment.strip_prefix("sprite")?;
+                (name.is_empty() || name.starts_with(' ')).then(|| (i + 1, name.trim().to_string()))
+            })
+            .collect();
+        markers.insert(file.as_str(), lines);
+    }
+    markers
+}
+
+/// ASCII previews of the sprites in db data, drawing set bits as `#`. Each run of consecutive
+/// db lines is one sprite named after the label before it, and a `; sprite [name]` comment
+/// starts a new one.
+pub fn sprites(assembly: &Assembly) -> String {
+    let markers = sprite_markers(&assembly.files, assembly.options.comment);
+    // (title, rows) of every sprite found so far
+    let mut sprites: Vec<(String, Vec<u8>)> = Vec::new();
+    let mut in_sprite = false;
+    let mut label: Option<&str> = None;
+    let mut previous_line = 0;
+    for (entry, offset, span) in assembly.instructions.iter() {
+        let marker = markers.get(span.file.as_str()).and_then(|lines| {
+            lines
+                .iter()
+                .find(|(line, _)| *line > previous_line && *line <= span.line)
+        });
+        previous_line = span.line;
+
+        let dir = match entry {
+            AsmEnum::Directive(dir) if dir.mnemonic.eq_ignore_ascii_case("db") => dir,
+            AsmEnum::Label(l) => {
+                in_sprite = false;
+                label = Some(&l.name);
+                continue;
+            }
+            _ => {
+                in_sprite = false;
+                label = None;
+                continue;
+            }
+        };
+        if !in_sprite || marker.is_some() {
+            let name = match marker {
+                Some((_, name)) if !name.is_empty() => Some(name.as_str()),
+                _ => label,
+            };
+            let title = match name {
+                Some(name) => format!("{:#06x} {} ({})", offset, name, span),
+                None => format!("{:#06x} ({})", offset, span),
+            };
+            sprites.push((title, Vec::new()));
+            in_sprite = true;
+            label = None;
+        }
+        let rows = &mut sprites.last_mut().unwrap().1;
+        rows.extend(
+            dir.args
+                .iter()
+                .filter_map(|a| Operand::parse_byte_str(a).ok()),
+        );
+    }
+
+    let mut out = String::new();
+    for (title, rows) in sprites {
+        out.push_str(&title);
+        out.push('\n');
+        for row in rows {
+            let pixels: String = (0..8)
+                .map(|bit| if row & (0x80 >> bit) != 0 { '#' } else { ' ' })
+                .collect();
+            out.push_str(pixels.trim_end());
+            out.push('\n');
+        }
+    }
+    out
+}
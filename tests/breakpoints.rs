@@ -0,0 +1,33 @@
+//! Checks the CSV of code labels written for emulator breakpoints.
+
+use chip8_assembler::asm::assemble_lines;
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+use chip8_assembler::report::breakpoints;
+
+#[test]
+fn lists_code_labels_only() {
+    let source = [
+        "define SPEED 2",
+        "start:",
+        "    LD I, sprite",
+        "    CALL draw",
+        "    JP start",
+        "draw:",
+        "define ROWS 1",
+        "    DRW V0, V1, ROWS",
+        "    RET",
+        "sprite:",
+        "    db 0xFF",
+        "speed: db SPEED",
+    ];
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source.iter().map(|l| l.to_string()),
+        "breakpoints.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    assert_eq!(breakpoints(&assembly), "start,0x0200\ndraw,0x0206\n");
+}
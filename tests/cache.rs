@@ -0,0 +1,51 @@
+//! Reassembles with a parse cache and counts which files are parsed again.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use chip8_assembler::asm::{generate_full_asm_cached, ParseCache};
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+
+fn build(main: &Path, cache: &mut ParseCache) -> Vec<u8> {
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm_cached(
+        &[main.to_string_lossy().into_owned()],
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+        cache,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    bytes
+}
+
+#[test]
+fn only_changed_files_are_parsed_again() {
+    let dir = env::temp_dir().join(format!("chip8-cache-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let main = dir.join("main.asm");
+    fs::write(&main, "define N 2\n    CLS\ninclude a.asm\n").unwrap();
+    fs::write(dir.join("a.asm"), "    RET\ninclude b.asm\n").unwrap();
+    fs::write(dir.join("b.asm"), "times N db 1\n").unwrap();
+
+    let mut cache = ParseCache::default();
+    assert_eq!(build(&main, &mut cache), [0x00, 0xE0, 0x00, 0xEE, 1, 1]);
+    assert_eq!(cache.parses, 3);
+
+    // Nothing changed, so nothing is parsed
+    build(&main, &mut cache);
+    assert_eq!(cache.parses, 3);
+
+    fs::write(dir.join("a.asm"), "    EXIT\ninclude b.asm\n").unwrap();
+    assert_eq!(build(&main, &mut cache), [0x00, 0xE0, 0x00, 0xFD, 1, 1]);
+    assert_eq!(cache.parses, 4);
+
+    // b.asm is unchanged, but its times count uses a define from main.asm that did change
+    fs::write(&main, "define N 3\n    CLS\ninclude a.asm\n").unwrap();
+    assert_eq!(build(&main, &mut cache), [0x00, 0xE0, 0x00, 0xFD, 1, 1, 1]);
+    assert_eq!(cache.parses, 6);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,297 @@
+//! Runs the command line tool on small programs and checks what it prints.
+
+use std::env;
+use std::fs;
+use std::process::{Command, Output};
+
+/// Runs the assembler with `args` followed by a file holding `source`
+fn run(name: &str, source: &str, args: &[&str]) -> Output {
+    let path = source_file(name, source);
+    run_args(&[args, &[path.as_str()]].concat())
+}
+
+/// Writes `source` to a file in the temp directory, returning its path
+fn source_file(name: &str, source: &str) -> String {
+    let path = env::temp_dir().join(format!("chip8-cli-{}-{}.asm", name, std::process::id()));
+    fs::write(&path, source).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+fn run_args(args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_chip8_assembler"))
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn quiet_hides_warnings_but_not_errors() {
+    // SYS is deprecated and warned about, the LD has no valid form
+    let source = "    SYS 0x300\n    LD V0\n";
+
+    let loud = run("loud", source, &["--check", "--no-color"]);
+    let stderr = String::from_utf8_lossy(&loud.stderr);
+    assert!(stderr.contains("warning: SYS is ignored"), "{}", stderr);
+    assert!(stderr.contains("error:"), "{}", stderr);
+
+    let quiet = run("quiet", source, &["--check", "--no-color", "--quiet"]);
+    let stderr = String::from_utf8_lossy(&quiet.stderr);
+    assert!(!stderr.contains("warning"), "{}", stderr);
+    assert!(stderr.contains("error:"), "{}", stderr);
+    assert_eq!(quiet.status.code(), Some(1));
+
+    let clean = run("clean", "    SYS 0x300\n", &["--check", "--quiet"]);
+    assert!(clean.stderr.is_empty());
+    assert!(clean.status.success());
+}
+
+#[test]
+fn decodes_opcode_words() {
+    let decoded = run_args(&["--decode", "0x6001"]);
+    assert_eq!(String::from_utf8_lossy(&decoded.stdout), "LD V0, 1\n");
+
+    let decoded = run_args(&["--decode", "0xD125", "0x00E0"]);
+    assert_eq!(
+        String::from_utf8_lossy(&decoded.stdout),
+        "DRW V1, V2, 5\nCLS\n"
+    );
+
+    let unknown = run_args(&["--decode", "0xFFFF"]);
+    assert!(String::from_utf8_lossy(&unknown.stderr).contains("0xffff is not an instruction"));
+    assert_eq!(unknown.status.code(), Some(1));
+}
+
+#[test]
+fn werror_fails_on_warnings() {
+    // COUNT is never used, which is only a warning
+    let source = "define COUNT 3\n    CLS\n";
+
+    let lenient = run("lenient", source, &["--check", "--no-color"]);
+    assert!(String::from_utf8_lossy(&lenient.stderr).contains("warning:"));
+    assert!(lenient.status.success());
+
+    let strict = run("strict", source, &["--check", "--no-color", "--werror"]);
+    let stderr = String::from_utf8_lossy(&strict.stderr);
+    assert!(stderr.contains("error:"), "{}", stderr);
+    assert!(!stderr.contains("warning:"), "{}", stderr);
+    assert_eq!(strict.status.code(), Some(1));
+}
+
+#[test]
+fn print_tree_shows_entries_before_resolving() {
+    let source = "define SPEED 3\nstart: LD V0, SPEED\n    db 1, 2\n    JP start\n";
+    let output = run("tree", source, &["--print-tree"]);
+    assert!(output.status.success());
+    // Drop the temporary file's path from each line
+    let tree: Vec<String> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|l| l.split_once(".asm:").unwrap().1.to_string())
+        .collect();
+    assert_eq!(
+        tree,
+        [
+            "1: Define {key: 'SPEED', value: '3'}",
+            "2: Label {name: 'start'}",
+            "2: Instruction {mnemonic: 'LD', args: [V0, SPEED], byte_size: 2}",
+            "3: Directive {mnemonic: 'db', args: [1, 2], byte_size: 2}",
+            "4: Instruction {mnemonic: 'JP', args: [start], byte_size: 2}",
+        ]
+    );
+}
+
+#[test]
+fn c_attribute_follows_the_array_name() {
+    let path = source_file("carray", "    CLS\n");
+
+    let plain = run_args(&["--format", "carray", &path, "-"]);
+    let plain = String::from_utf8_lossy(&plain.stdout).into_owned();
+    assert!(
+        plain.contains("const unsigned char rom[2] = {"),
+        "{}",
+        plain
+    );
+
+    let progmem = run_args(&["--format", "carray", "--c-attribute", "PROGMEM", &path, "-"]);
+    let progmem = String::from_utf8_lossy(&progmem.stdout).into_owned();
+    assert!(
+        progmem.contains("const unsigned char rom[2] PROGMEM = {\n    0x00, 0xE0,\n};"),
+        "{}",
+        progmem
+    );
+
+    let raw = run_args(&["--c-attribute", "PROGMEM", &path, "-"]);
+    assert_eq!(raw.status.code(), Some(2));
+}
+
+#[test]
+fn underline_stops_at_the_configured_comment() {
+    // Missing an operand, so no one token is to blame
+    let source = "    LD V0 # not code\n";
+    let output = run(
+        "comment-char",
+        source,
+        &["--check", "--no-color", "--comment-char", "#"],
+    );
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("1 |     LD V0 # not code\n"), "{}", stderr);
+    assert!(stderr.contains("  |     ^^^^^\n"), "{}", stderr);
+}
+
+#[test]
+fn check_reports_errors_without_writing() {
+    let output = run("check", "    JP nowhere\n", &["--check", "--no-color"]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains("error: Undefined label or define: nowhere"),
+        "{}",
+        stderr
+    );
+    assert_eq!(output.status.code(), Some(1));
+    assert!(output.stdout.is_empty());
+
+    // Every path is a source in check mode, so nothing is taken as the output
+    let path = source_file("check-ok", "    CLS\n");
+    let output = run_args(&["--check", &path, &path]);
+    assert!(output.status.success());
+    assert!(output.stdout.is_empty());
+
+    // Nor are the maps written
+    let symbols = env::temp_dir().join(format!("chip8-cli-check-{}.csv", std::process::id()));
+    let output = run_args(&["--check", "--symbol-map", &symbols.to_string_lossy(), &path]);
+    assert!(output.status.success());
+    assert!(!symbols.exists());
+}
+
+#[test]
+fn exit_codes() {
+    let broken = run("broken", "    FROB V0\n", &["--check", "--no-color"]);
+    assert_eq!(broken.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&broken.stderr);
+    assert!(stderr.contains("Unknown mnemonic 'FROB'"), "{}", stderr);
+    assert!(!stderr.contains("panicked"), "{}", stderr);
+
+    let missing = run_args(&["--check", "no/such/file.asm"]);
+    assert_eq!(missing.status.code(), Some(1));
+
+    let usage = run_args(&[]);
+    assert_eq!(usage.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&usage.stderr).contains("Usage:"));
+}
+
+#[test]
+fn writes_the_output_to_stdout() {
+    let path = source_file("stdout", "    CLS\n    RET\n");
+    let raw = run_args(&[&path, "-"]);
+    assert!(raw.status.success());
+    assert_eq!(raw.stdout, [0x00, 0xE0, 0x00, 0xEE]);
+
+    let hex = run_args(&["--format", "ihex", &path, "-"]);
+    assert_eq!(
+        String::from_utf8_lossy(&hex.stdout),
+        ":0402000000E000EE2C\n:00000001FF\n"
+    );
+}
+
+#[test]
+fn pad_to_fills_with_zeros() {
+    let path = source_file("pad", "    db 1, 2, 3, 4, 5, 6, 7, 8, 9, 10\n");
+    let padded = run_args(&["--pad-to", "16", &path, "-"]);
+    assert!(padded.status.success());
+    assert_eq!(
+        padded.stdout,
+        [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 0, 0, 0, 0, 0, 0]
+    );
+
+    let too_small = run_args(&["--no-color", "--pad-to", "8", &path, "-"]);
+    assert_eq!(too_small.status.code(), Some(1));
+    let stderr = String::from_utf8_lossy(&too_small.stderr);
+    assert!(
+        stderr.contains("Program is 10 bytes, larger than --pad-to 8"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn renders_the_line_under_the_error() {
+    let path = source_file("render", "    CLS\n    FROB V0 ; not an instruction\n");
+    let output = run_args(&["--check", "--no-color", &path]);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!(
+            "\
+{}:2: error: Unknown mnemonic 'FROB'
+  |
+2 |     FROB V0 ; not an instruction
+  |     ^^^^
+",
+            path
+        )
+    );
+
+    // The caret goes under the operand that does not fit
+    let path = source_file("render-operand", "start:   LD V0, 300\n");
+    let output = run_args(&["--check", "--no-color", &path]);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.ends_with("1 | start:   LD V0, 300\n  |                 ^^^\n"),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn hex_load_offset() {
+    let path = source_file("offset", "start:\n    JP start\n");
+    let output = run_args(&[&path, "-", "0x300"]);
+    assert!(output.status.success());
+    assert_eq!(output.stdout, [0x13, 0x00]);
+
+    let invalid = run_args(&[&path, "-", "0xZZ"]);
+    assert_eq!(invalid.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&invalid.stderr).contains("Invalid offset: 0xZZ"));
+}
+
+#[test]
+fn hex_output_path_writes_intel_hex() {
+    let path = source_file("ihex", "    CLS\n");
+    let out = env::temp_dir().join(format!("chip8-cli-ihex-{}.hex", std::process::id()));
+    let output = run_args(&[&path, &out.to_string_lossy()]);
+    assert!(output.status.success());
+    assert_eq!(
+        fs::read_to_string(&out).unwrap(),
+        ":0202000000E01C\n:00000001FF\n"
+    );
+    fs::remove_file(&out).unwrap();
+}
+
+#[test]
+fn verbose_prints_resolved_entries() {
+    let path = source_file(
+        "verbose",
+        "define N 3\nstart:\n    LD V0, N\n    JP start\n",
+    );
+    let output = run_args(&["--verbose", &path, "-"]);
+    assert!(output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    // Labels and defines are shown as the values they resolved to
+    assert!(
+        stderr.contains(
+            "0x0200 Label {name: 'start'}\n\
+             0x0200 Instruction {mnemonic: 'LD', args: [V0, 3], byte_size: 2}\n\
+             0x0202 Instruction {mnemonic: 'JP', args: [512], byte_size: 2}\n"
+        ),
+        "{}",
+        stderr
+    );
+}
+
+#[test]
+fn unknown_flag_is_a_usage_error() {
+    let first = source_file("typo-first", "    CLS\n");
+    let second = source_file("typo-second", "    RET\n");
+    let output = run_args(&["--chek", &first, &second]);
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("Unknown flag: --chek"));
+    assert_eq!(fs::read_to_string(&second).unwrap(), "    RET\n");
+}
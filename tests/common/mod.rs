@@ -0,0 +1,69 @@
+//! Helpers shared by the integration tests, which assemble source lines held in memory.
+// Each test file uses only some of them
+#![allow(dead_code)]
+
+use chip8_assembler::asm::{assemble_lines, Assembly};
+use chip8_assembler::diagnostics::{Diagnostics, Severity};
+use chip8_assembler::error::AssemblyError;
+use chip8_assembler::options::AssemblerOptions;
+
+/// Assembles `source` with `options`, returning the program, its bytes and what was reported
+pub fn assemble_with(
+    source: &[&str],
+    options: &AssemblerOptions,
+) -> (Assembly, Vec<u8>, Diagnostics) {
+    let mut diagnostics = Diagnostics::new();
+    let lines = source.iter().map(|l| l.to_string());
+    let assembly = assemble_lines(lines, "test.asm", options, &mut diagnostics);
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    (assembly, bytes, diagnostics)
+}
+
+/// Bytes of `source` assembled with the default options, which must not fail
+pub fn assemble(source: &[&str]) -> Vec<u8> {
+    assemble_ok(source, &AssemblerOptions::default())
+}
+
+/// Bytes of `source` assembled with `options`, which must not fail
+pub fn assemble_ok(source: &[&str], options: &AssemblerOptions) -> Vec<u8> {
+    let (_, bytes, diagnostics) = assemble_with(source, options);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    bytes
+}
+
+/// Messages of the errors assembling `source` with `options` reports
+pub fn errors(source: &[&str], options: &AssemblerOptions) -> Vec<String> {
+    messages(source, options, Severity::Error)
+}
+
+/// The errors assembling `source` with `options` reports, to match on their kind
+pub fn assembly_errors(source: &[&str], options: &AssemblerOptions) -> Vec<AssemblyError> {
+    let (_, _, diagnostics) = assemble_with(source, options);
+    diagnostics.errors().cloned().collect()
+}
+
+/// Messages of the warnings assembling `source` with `options` reports
+pub fn warnings(source: &[&str], options: &AssemblerOptions) -> Vec<String> {
+    messages(source, options, Severity::Warning)
+}
+
+fn messages(source: &[&str], options: &AssemblerOptions, severity: Severity) -> Vec<String> {
+    let (_, _, diagnostics) = assemble_with(source, options);
+    diagnostics
+        .items
+        .into_iter()
+        .filter(|d| d.severity == severity)
+        .map(|d| d.message)
+        .collect()
+}
+
+/// Lines of `source` that get a warning starting with `prefix` when assembled with `options`
+pub fn warned_lines(source: &[&str], options: &AssemblerOptions, prefix: &str) -> Vec<usize> {
+    let (_, _, diagnostics) = assemble_with(source, options);
+    diagnostics
+        .items
+        .iter()
+        .filter(|d| d.severity == Severity::Warning && d.message.starts_with(prefix))
+        .map(|d| d.span.as_ref().unwrap().line)
+        .collect()
+}
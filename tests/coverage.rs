@@ -0,0 +1,52 @@
+//! Checks the opcode coverage report against a program using a handful of instructions.
+
+use std::env;
+use std::fs;
+
+use chip8_assembler::asm::generate_full_asm;
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+use chip8_assembler::report::{opcode_coverage, opcode_usage};
+
+const SOURCE: &str = "\
+start:
+    LD V0, 5
+    LD I, sprite
+    DRW V0, V0, 1
+    MOV V1, V0
+    JP start
+sprite:
+    db 0xFF
+";
+
+#[test]
+fn coverage_of_jp_ld_and_drw() {
+    let path = env::temp_dir().join(format!("chip8-coverage-{}.asm", std::process::id()));
+    fs::write(&path, SOURCE).unwrap();
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm(
+        &[path.to_string_lossy().into_owned()],
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+
+    let covered: Vec<(&str, usize)> = opcode_usage(&assembly, &bytes)
+        .into_iter()
+        .filter(|(_, n)| *n > 0)
+        .map(|(info, n)| (info.opcode, n))
+        .collect();
+    // MOV shares 8xy0 with LD Vx, Vy, and the db byte is not an instruction
+    assert_eq!(
+        covered,
+        [
+            ("1nnn", 1),
+            ("6xkk", 1),
+            ("8xy0", 1),
+            ("Annn", 1),
+            ("Dxyn", 1)
+        ]
+    );
+    assert!(opcode_coverage(&assembly, &bytes).starts_with("Covered 5 of "));
+}
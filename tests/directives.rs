@@ -0,0 +1,89 @@
+//! Emits the data directives and checks the values they reject.
+
+mod common;
+
+use chip8_assembler::options::AssemblerOptions;
+
+#[test]
+fn db_values_fit_a_byte() {
+    assert_eq!(
+        common::assemble(&["    db 255, 0xFF, -1, -128"]),
+        [0xFF, 0xFF, 0xFF, 0x80]
+    );
+    let options = AssemblerOptions::default();
+    assert_eq!(
+        common::errors(&["    db 256"], &options),
+        ["Unable to convert to bytes: Value does not fit in a byte: 256"]
+    );
+    assert_eq!(
+        common::errors(&["    db -129"], &options),
+        ["Unable to convert to bytes: Value does not fit in a byte: -129"]
+    );
+}
+
+#[test]
+fn dn_packs_nibbles() {
+    assert_eq!(common::assemble(&["    dn 1, 2, 3"]), [0x12, 0x30]);
+    assert_eq!(common::assemble(&["    dn 0xA, 0xB"]), [0xAB]);
+    assert_eq!(
+        common::errors(&["    dn 1, 16"], &AssemblerOptions::default()),
+        ["Value does not fit in a nibble: 16"]
+    );
+}
+
+#[test]
+fn db_length_between_labels() {
+    let source = [
+        "    db end - start",
+        "start:",
+        "    db 1, 2, 3",
+        "    CLS",
+        "end:",
+    ];
+    assert_eq!(common::assemble(&source), [5, 1, 2, 3, 0x00, 0xE0]);
+}
+
+#[test]
+fn trailing_comma_before_a_label() {
+    // The label is not swallowed as a continuation, and the dangling comma is warned about
+    let source = [
+        "first:",
+        "    db 1, 2,",
+        "second:",
+        "    db 3",
+        "    LD I, second",
+    ];
+    let options = AssemblerOptions::default();
+    assert_eq!(common::assemble(&source), [1, 2, 3, 0xA2, 0x02]);
+    assert_eq!(
+        common::warned_lines(&source, &options, "Trailing comma"),
+        [2]
+    );
+
+    assert_eq!(
+        common::warnings(&["    db 1,"], &options),
+        ["Trailing comma is not followed by more values"]
+    );
+}
+
+#[test]
+fn comment_char_inside_text() {
+    let source = ["    text \"a;b\" ; a real comment"];
+    assert_eq!(common::assemble(&source), [b'a', b';', b'b', 0]);
+
+    let hash = AssemblerOptions {
+        comment: '#',
+        ..AssemblerOptions::default()
+    };
+    let source = ["    text \"a#b;c\" # a real comment"];
+    assert_eq!(
+        common::assemble_ok(&source, &hash),
+        [b'a', b'#', b'b', b';', b'c', 0]
+    );
+}
+
+#[test]
+fn here_names_the_current_address() {
+    let source = ["    LD I, table", "    CLS", "here table", "    db 1, 2"];
+    assert_eq!(common::assemble(&source), [0xA2, 0x04, 0x00, 0xE0, 1, 2]);
+}
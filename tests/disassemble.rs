@@ -0,0 +1,80 @@
+//! Disassembles an assembled ROM with its symbol map and checks the labels come back.
+
+mod common;
+
+use std::env;
+use std::fs;
+
+use chip8_assembler::asm::generate_full_asm;
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::disassemble::{disassemble, parse_symbol_map};
+use chip8_assembler::options::{AssemblerOptions, Target};
+use chip8_assembler::report::symbol_map;
+
+const SOURCE: &str = "\
+    LD V0, 0
+loop:
+    ADD V0, 1
+    SE V0, 10
+    JP loop
+    CALL 0x300
+";
+
+/// Assembles `source` from a file in the temp directory, returning the bytes and symbol map
+fn assemble(name: &str, source: &str) -> (Vec<u8>, String) {
+    let path = env::temp_dir().join(format!(
+        "chip8-disassemble-{}-{}.asm",
+        name,
+        std::process::id()
+    ));
+    fs::write(&path, source).unwrap();
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm(
+        &[path.to_string_lossy().into_owned()],
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    (bytes, symbol_map(&assembly))
+}
+
+#[test]
+fn named_loop_is_labelled() {
+    let (bytes, map) = assemble("loop", SOURCE);
+    let symbols = parse_symbol_map(&map).unwrap();
+    let listing = disassemble(&bytes, 0x200, &symbols, Target::SChip);
+
+    assert!(listing.contains("loop:\n    ADD V0, 1"), "{}", listing);
+    assert!(listing.contains("JP loop"), "{}", listing);
+    // No symbol at 0x300, so the address stays in hex
+    assert!(listing.contains("CALL 0x300"), "{}", listing);
+
+    let (reassembled, _) = assemble("listing", &listing);
+    assert_eq!(reassembled, bytes);
+}
+
+#[test]
+fn verify_round_trips_a_correct_program() {
+    let source = [
+        "start:",
+        "    LD V0, 0x12",
+        "    LD I, sprite",
+        "    DRW V0, V1, 1",
+        "    SKP V2",
+        "    CALL start",
+        "    SHR V1, V2",
+        "    JP V0, start",
+        "sprite:",
+        "    db 0xFF",
+    ];
+    let (assembly, bytes, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(assembly.verify(&bytes), Ok(()));
+
+    // Cut short, the last instruction has nothing to decode
+    let cut = &bytes[..bytes.len() - 3];
+    assert_eq!(
+        assembly.verify(cut),
+        Err("0x020c: past the end of the output".to_string())
+    );
+}
@@ -0,0 +1,57 @@
+//! The opt-in check for DRW running before I is set.
+
+mod common;
+
+use chip8_assembler::options::AssemblerOptions;
+
+/// Lines of `source` that get a warning with `--check-draws`
+fn flagged(source: &[&str]) -> Vec<usize> {
+    let options = AssemblerOptions {
+        check_draws: true,
+        ..AssemblerOptions::default()
+    };
+    common::warned_lines(source, &options, "DRW")
+}
+
+#[test]
+fn flags_draw_without_ld_i() {
+    let source = [
+        "    LD V0, 1",
+        "    DRW V0, V1, 5",
+        "    LD I, sprite",
+        "    DRW V0, V1, 5",
+        "    JP end",
+        "sprite:",
+        "    db 0xF0, 0x90, 0x90, 0x90, 0xF0",
+        "end:",
+        "    EXIT",
+    ];
+    assert_eq!(flagged(&source), [2]);
+}
+
+#[test]
+fn follows_jumps_but_trusts_subroutines() {
+    let source = [
+        "    CALL setup",
+        "loop:",
+        "    DRW V0, V1, 5",
+        "    JP loop",
+        "setup:",
+        "    LD I, sprite",
+        "    RET",
+        "sprite:",
+        "    db 0xF0, 0x90, 0x90, 0x90, 0xF0",
+    ];
+    assert!(flagged(&source).is_empty());
+
+    // The first time round the loop draws before I is loaded
+    let source = [
+        "loop:",
+        "    DRW V0, V1, 5",
+        "    LD I, sprite",
+        "    JP loop",
+        "sprite:",
+        "    db 0xF0, 0x90, 0x90, 0x90, 0xF0",
+    ];
+    assert_eq!(flagged(&source), [2]);
+}
@@ -0,0 +1,164 @@
+//! Matches on the kinds of error assembling reports.
+
+mod common;
+
+use chip8_assembler::error::AssemblyError;
+use chip8_assembler::options::AssemblerOptions;
+
+#[test]
+fn undefined_symbol() {
+    let errors = common::assembly_errors(&["    JP nowhere"], &AssemblerOptions::default());
+    match errors.as_slice() {
+        [AssemblyError::UndefinedSymbol {
+            span: Some(span),
+            name,
+        }] => {
+            assert_eq!(name, "nowhere");
+            assert_eq!(span.line, 1);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn duplicate_symbol() {
+    let source = ["start:", "    CLS", "start:", "    JP start"];
+    let errors = common::assembly_errors(&source, &AssemblerOptions::default());
+    match errors.as_slice() {
+        [AssemblyError::DuplicateSymbol {
+            span: Some(span),
+            name,
+            first: Some(first),
+        }] => {
+            assert_eq!(name, "start");
+            assert_eq!((first.line, span.line), (1, 3));
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn unknown_mnemonic_and_range() {
+    let options = AssemblerOptions {
+        max_rom_size: Some(2),
+        ..AssemblerOptions::default()
+    };
+    let errors = common::assembly_errors(&["    CLS", "    FROB V0"], &options);
+    assert!(
+        errors.iter().any(|e| matches!(
+            e,
+            AssemblyError::UnknownMnemonic { mnemonic, .. } if mnemonic == "FROB"
+        )),
+        "{:?}",
+        errors
+    );
+    assert!(
+        errors
+            .iter()
+            .any(|e| matches!(e, AssemblyError::RangeExceeded { span: None, .. })),
+        "{:?}",
+        errors
+    );
+}
+
+#[test]
+fn includes_are_io_errors() {
+    let errors = common::assembly_errors(&["include \"other.asm\""], &AssemblerOptions::default());
+    assert!(
+        matches!(errors.as_slice(), [AssemblyError::Io { path, .. }] if path == "other.asm"),
+        "{:?}",
+        errors
+    );
+}
+
+#[test]
+fn define_provenance_names_the_define_that_failed() {
+    let source = ["define A 1", "define B 0x1ZZ", "    db A, B"];
+    let errors = common::assembly_errors(&source, &AssemblerOptions::default());
+    match errors.as_slice() {
+        [AssemblyError::Parse {
+            span: Some(span),
+            message,
+            token,
+        }] => {
+            assert_eq!(span.line, 3);
+            assert_eq!(token.as_deref(), Some("B"));
+            assert!(
+                message.ends_with(", value '0x1ZZ' (from define B at line 2)"),
+                "{}",
+                message
+            );
+            assert!(!message.contains("define A"), "{}", message);
+        }
+        other => panic!("{:?}", other),
+    }
+}
+
+#[test]
+fn mnemonics_are_reserved() {
+    let options = AssemblerOptions::default();
+    let cases = [
+        (
+            ["ADD:", "    CLS"],
+            "'ADD' is reserved and cannot be used as a label",
+        ),
+        (
+            ["define LD 3", "    CLS"],
+            "'LD' is reserved and cannot be used as a define",
+        ),
+    ];
+    for (source, message) in cases {
+        let errors = common::assembly_errors(&source, &options);
+        match errors.as_slice() {
+            [error @ AssemblyError::Parse {
+                span: Some(span), ..
+            }] => {
+                assert_eq!(error.to_string(), message);
+                assert_eq!(span.line, 1);
+            }
+            other => panic!("{:?}", other),
+        }
+    }
+}
+
+#[test]
+fn json_for_an_undefined_symbol() {
+    let source = ["    JP nowhere"];
+    let (_, _, diagnostics) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        diagnostics.to_json(),
+        r#"[{"severity":"error","message":"Undefined label or define: nowhere","file":"test.asm","line":1,"column":8}]"#
+    );
+}
+
+#[test]
+fn column_is_the_operand_at_fault() {
+    // Past the label and register, at the byte that does not fit
+    let source = ["loop: LD V1, 0x100"];
+    let (_, _, diagnostics) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(diagnostics.items.len(), 1);
+    assert_eq!(diagnostics.items[0].column(), Some(14));
+}
+
+#[test]
+fn defines_before_their_definition() {
+    // Operands see every define, wherever it is
+    let source = ["    LD V0, SPEED", "define SPEED 3"];
+    assert!(common::assembly_errors(&source, &AssemblerOptions::default()).is_empty());
+
+    // Preprocessing such as times counts is done in order, so it cannot look ahead
+    let source = ["times N db 0", "define N 2"];
+    let errors = common::assembly_errors(&source, &AssemblerOptions::default());
+    match errors.as_slice() {
+        [error @ AssemblyError::Parse {
+            span: Some(span), ..
+        }] => {
+            assert_eq!(
+                error.to_string(),
+                "'N' is used in a times count before it is defined at test.asm:2"
+            );
+            assert_eq!(span.line, 1);
+        }
+        other => panic!("{:?}", other),
+    }
+}
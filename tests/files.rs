@@ -0,0 +1,155 @@
+//! Assembles programs spread over several source files on disk.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8_assembler::asm::{expand, generate_full_asm, Assembly};
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+
+/// Writes each name and source pair into a fresh directory in the temp directory
+fn project(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = env::temp_dir().join(format!("chip8-files-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    for (file, source) in files {
+        fs::create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+        fs::write(dir.join(file), source).unwrap();
+    }
+    dir
+}
+
+/// Assembles `paths` from `dir` in order as one program, which must not fail
+fn build(dir: &Path, paths: &[&str]) -> (Assembly, Vec<u8>) {
+    let paths: Vec<String> = paths
+        .iter()
+        .map(|p| dir.join(p).to_string_lossy().into_owned())
+        .collect();
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm(&paths, &AssemblerOptions::default(), &mut diagnostics);
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    (assembly, bytes)
+}
+
+#[test]
+fn sources_are_one_program() {
+    let dir = project(
+        "many",
+        &[
+            ("main.asm", "start:\n    CALL draw\n    JP start\n"),
+            ("draw.asm", "draw:\n    DRW V0, V1, 1\n    RET\n"),
+        ],
+    );
+    let (assembly, bytes) = build(&dir, &["main.asm", "draw.asm"]);
+    assert_eq!(bytes, [0x22, 0x04, 0x12, 0x00, 0xD0, 0x11, 0x00, 0xEE]);
+    assert_eq!(assembly.files.len(), 2);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn shared_header_is_read_once_among_500_includes() {
+    let mut files: Vec<(String, String)> = (0..500)
+        .map(|i| {
+            let name = format!("part{}.asm", i);
+            (name, "include \"shared.asm\"\n    db ONE\n".to_string())
+        })
+        .collect();
+    let main: String = files
+        .iter()
+        .map(|(name, _)| format!("include \"{}\"\n", name))
+        .collect();
+    files.push(("main.asm".to_string(), main));
+    files.push(("shared.asm".to_string(), "define ONE 1\n".to_string()));
+    let files: Vec<(&str, &str)> = files
+        .iter()
+        .map(|(name, source)| (name.as_str(), source.as_str()))
+        .collect();
+    let dir = project("includes", &files);
+
+    let (assembly, bytes) = build(&dir, &["main.asm"]);
+    assert_eq!(bytes, [1; 500]);
+    assert_eq!(assembly.files.len(), 502);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn expand_flattens_includes_and_times() {
+    let dir = project(
+        "expand",
+        &[
+            (
+                "main.asm",
+                "    CLS\ntimes 2 db 0xFF\ninclude \"draw.asm\"\n",
+            ),
+            ("draw.asm", "draw: DRW V0, V1, 1\n"),
+        ],
+    );
+    let mut diagnostics = Diagnostics::new();
+    let main = dir.join("main.asm").to_string_lossy().into_owned();
+    let expanded = expand(&[main], &AssemblerOptions::default(), &mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    // Drop the temporary directory from each annotation
+    let prefix = format!("{}/", dir.to_string_lossy());
+    let expanded = expanded.replace(&prefix, "");
+    assert_eq!(
+        expanded.lines().collect::<Vec<&str>>(),
+        [
+            "    CLS                          ; main.asm:1",
+            "    db 0xFF                      ; main.asm:2",
+            "    db 0xFF                      ; main.asm:2",
+            "draw:                            ; draw.asm:1",
+            "    DRW V0, V1, 1                ; draw.asm:1",
+        ]
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn forward_reference_into_a_later_include() {
+    let dir = project(
+        "forward",
+        &[
+            (
+                "main.asm",
+                "    CALL draw\n    JP end\ninclude \"draw.asm\"\nend:\n    EXIT\n",
+            ),
+            ("draw.asm", "    db 1\ndraw:\n    RET\n"),
+        ],
+    );
+    let (assembly, bytes) = build(&dir, &["main.asm"]);
+    // Includes follow the file that includes them, after its last line
+    let draw = assembly
+        .instructions
+        .iter()
+        .find_map(|(entry, offset, _)| (entry.label_name() == Some("draw")).then_some(*offset));
+    assert_eq!(draw, Some(0x207));
+    assert_eq!(bytes, [0x22, 0x07, 0x12, 0x04, 0x00, 0xFD, 1, 0x00, 0xEE]);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn diamond_include_through_a_parent_path() {
+    let dir = project(
+        "diamond",
+        &[
+            (
+                "main.asm",
+                "include \"lib/common.asm\"\ninclude \"lib/gfx/draw.asm\"\ninclude \"./lib/common.asm\"\n",
+            ),
+            ("lib/common.asm", "common:\n    db 1\n"),
+            ("lib/gfx/draw.asm", "include \"../common.asm\"\n    db 2\n"),
+        ],
+    );
+    let (assembly, mut bytes) = build(&dir, &["main.asm"]);
+    bytes.sort();
+    assert_eq!(bytes, [1, 2]);
+    assert_eq!(assembly.files.len(), 3);
+    // Named as the file it is rather than by the way it was reached
+    assert!(assembly
+        .files
+        .iter()
+        .any(|f| f.ends_with("/lib/common.asm")));
+    assert!(!assembly.files.iter().any(|f| f.contains("..")));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,47 @@
+//! The opt-in check for a value in VF overwritten by a flag before it is read.
+
+mod common;
+
+use chip8_assembler::options::AssemblerOptions;
+
+fn options() -> AssemblerOptions {
+    AssemblerOptions {
+        check_flags: true,
+        ..AssemblerOptions::default()
+    }
+}
+
+/// Lines of `source` that get a warning with `--check-flags`
+fn flagged(source: &[&str]) -> Vec<usize> {
+    common::warned_lines(source, &options(), "VF")
+}
+
+#[test]
+fn flags_vf_overwritten_before_read() {
+    let source = ["    LD VF, 5", "    ADD V0, V1", "    LD V2, VF"];
+    assert_eq!(flagged(&source), [1]);
+}
+
+#[test]
+fn vf_read_first_is_fine() {
+    let source = [
+        "    LD VF, 5",
+        "    LD V2, VF",
+        "    SUB V0, V1",
+        "    LD VF, 1",
+        "    SE V0, 0",
+        "    SHR V3",
+    ];
+    assert!(flagged(&source).is_empty());
+}
+
+#[test]
+fn incomplete_ld_is_an_error_not_a_panic() {
+    assert_eq!(
+        common::errors(&["    LD [I]", "    LD R"], &options()),
+        [
+            "LD expects 2 or 3 operands, found 1",
+            "LD expects 2 or 3 operands, found 1"
+        ]
+    );
+}
@@ -1,4 +1,4 @@
-; This will only run if you have implemented the custom instructions LD I, Vx, Vy and LD Vx, Vy, I
+; Uses the XO-CHIP instructions LD I, Vx, Vy and LD Vx, Vy, I, so assemble it with --target xochip
 
 include "utils.asm"
 include "font.asm"
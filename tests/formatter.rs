@@ -0,0 +1,35 @@
+//! Formats messy source into the canonical style.
+
+use chip8_assembler::formatter::format_source;
+
+const MESSY: &str = "\
+define  speed   v3
+start:   ld v0,5;set up
+  add   v0 ,  speed
+        drw v0,v1,  5   ;   draw it
+sprite: db %11110000 ,0x90,
+   0xF0
+  jp start
+";
+
+const CANONICAL: &str = "\
+define speed V3
+start:
+    LD   V0, 5 ; set up
+    ADD  V0, speed
+    DRW  V0, V1, 5 ; draw it
+sprite:
+    db %11110000, 0x90,
+        0xF0
+    JP   start
+";
+
+#[test]
+fn messy_source_becomes_canonical() {
+    assert_eq!(format_source(MESSY, ';'), CANONICAL);
+}
+
+#[test]
+fn canonical_source_is_unchanged() {
+    assert_eq!(format_source(CANONICAL, ';'), CANONICAL);
+}
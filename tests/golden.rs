@@ -0,0 +1,147 @@
+//! Assembles every `.asm` fixture in tests/golden and compares the output with the `.bin` file
+//! next to it. A file named after the fixture with a `--format` name as its extension, such as
+//! `data.python`, is compared with the output serialized in that format. A fixture with an
+//! `.err` file is expected to fail instead, with each error as `line: message` in that file.
+//! Run with `UPDATE_GOLDEN=1` to write the `.bin`, `.err` and existing format files from the
+//! current output.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8_assembler::asm::generate_full_asm;
+use chip8_assembler::diagnostics::{Diagnostics, Severity};
+use chip8_assembler::options::AssemblerOptions;
+use chip8_assembler::output::Format;
+
+/// Assembles one fixture with the default options, failing with its errors rendered
+fn assemble(path: &Path) -> Result<Vec<u8>, String> {
+    let mut diagnostics = Diagnostics::new();
+    let options = AssemblerOptions::default();
+    let assembly = generate_full_asm(
+        &[path.to_string_lossy().into_owned()],
+        &options,
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    if diagnostics.has_errors() {
+        let rendered = diagnostics
+            .items
+            .iter()
+            .map(|d| d.render(false, options.comment));
+        return Err(rendered.collect());
+    }
+    Ok(bytes)
+}
+
+/// Assembles a fixture that should fail, returning its errors as `line: message` lines
+fn assemble_errors(path: &Path) -> String {
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm(
+        &[path.to_string_lossy().into_owned()],
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    assembly.to_bytes(&mut diagnostics);
+    diagnostics
+        .items
+        .iter()
+        .filter(|d| d.severity == Severity::Error)
+        .map(|d| match &d.span {
+            Some(span) => format!("{}: {}\n", span.line, d.message),
+            None => format!("{}\n", d.message),
+        })
+        .collect()
+}
+
+/// Every golden file for `fixture` with the format it holds, the `.bin` file first
+fn golden_files_for(fixture: &Path) -> Vec<(PathBuf, Format)> {
+    let stem = fixture.file_stem().unwrap();
+    let mut golden = vec![(fixture.with_extension("bin"), Format::Raw)];
+    for entry in fs::read_dir(fixture.parent().unwrap()).unwrap() {
+        let path = entry.unwrap().path();
+        let extension = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or_default();
+        if path.file_stem() != Some(stem) || matches!(extension, "asm" | "bin") {
+            continue;
+        }
+        if let Some(format) = Format::from_name(extension) {
+            golden.push((path, format));
+        }
+    }
+    golden
+}
+
+fn fixtures() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/golden");
+    let mut fixtures: Vec<PathBuf> = fs::read_dir(dir)
+        .expect("tests/golden should exist")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|e| e == "asm"))
+        .collect();
+    fixtures.sort();
+    fixtures
+}
+
+#[test]
+fn golden_files() {
+    let update = env::var_os("UPDATE_GOLDEN").is_some();
+    let fixtures = fixtures();
+    assert!(!fixtures.is_empty(), "no fixtures in tests/golden");
+
+    let mut failures: Vec<String> = Vec::new();
+    for fixture in fixtures {
+        let err_file = fixture.with_extension("err");
+        if err_file.exists() {
+            let errors = assemble_errors(&fixture);
+            if update {
+                fs::write(&err_file, &errors).unwrap();
+                continue;
+            }
+            let expected = fs::read_to_string(&err_file).unwrap();
+            if expected != errors {
+                failures.push(format!(
+                    "{} errors differ from {}:\n  expected:\n{}  got:\n{}",
+                    fixture.display(),
+                    err_file.display(),
+                    expected,
+                    errors
+                ));
+            }
+            continue;
+        }
+        let bytes = match assemble(&fixture) {
+            Ok(bytes) => bytes,
+            Err(e) => {
+                failures.push(format!("{} failed to assemble:\n{}", fixture.display(), e));
+                continue;
+            }
+        };
+        for (golden, format) in golden_files_for(&fixture) {
+            let output = format.serialize(&bytes, AssemblerOptions::default().offset);
+            if update {
+                fs::write(&golden, &output).unwrap();
+                continue;
+            }
+            match fs::read(&golden) {
+                Ok(expected) if expected == output => {}
+                Ok(expected) => failures.push(format!(
+                    "{} differs from {}:\n  expected {:02X?}\n  got      {:02X?}",
+                    fixture.display(),
+                    golden.display(),
+                    expected,
+                    output
+                )),
+                Err(e) => failures.push(format!(
+                    "{}: unable to read {}: {} (run with UPDATE_GOLDEN=1 to create it)",
+                    fixture.display(),
+                    golden.display(),
+                    e
+                )),
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
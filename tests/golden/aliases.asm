@@ -0,0 +1,11 @@
+; Each pseudo-instruction followed by the instruction it stands for, so the bytes come in pairs
+    BCD V2
+    LD B, V2
+    FONT V3
+    LD F, V3
+    HIFONT V4
+    LD HF, V4
+    MOV V5, V6
+    LD V5, V6
+    font v7 ; with an operand this is FONT rather than the font directive
+    LD F, V7
@@ -0,0 +1,6 @@
+; Literals that are not one character, or an escape for one, are rejected
+    db 'AB'
+    LD V0, 'AB'
+    db '\q'
+    db '\x1'
+    dw ''
@@ -0,0 +1,5 @@
+; Character literals, including escapes for control characters
+    LD V0, 'A'
+    db 'A', 'z', ' ', ','
+    db '\0', '\t', '\n', '\r', '\\', '\x1b', '\xFF'
+    dw 'A'
@@ -0,0 +1,2 @@
+; The sample program, locked so changes to how bytes are emitted cannot alter it
+include "../cube.asm"
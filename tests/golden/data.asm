@@ -0,0 +1,10 @@
+; Data directives
+    db 0x01, #02, %00000011, 4
+    db -1, 'A'
+    dw 0x1234, 0xABCD
+    dn 1, 2, 3
+    text "Hi", ""
+    align 4
+    db 0xFF
+    offset 3
+    db 0xEE
@@ -0,0 +1,7 @@
+; Errors caused by a bad define value name the define
+define X 0xZZ
+define BIG 0x1000
+define OK 5
+    LD V0, X
+    db OK, X
+    LD I, BIG
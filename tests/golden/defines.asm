@@ -0,0 +1,9 @@
+; Defines for values and registers, including one used before it is defined
+define SPEED 3
+define POS v3
+
+    LD POS, SPEED
+    ADD POS, LATE
+    LD V1, POS
+    db SPEED + 1
+define LATE 0x10
@@ -0,0 +1,4 @@
+; Instructions without operands reject any they are given
+    CLS V0
+    RET 5
+    LD V0, 1, 2
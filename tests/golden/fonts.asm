@@ -0,0 +1,5 @@
+; The built-in hex digit fonts, 5 bytes a digit and 10 for the big ones
+small:
+    font
+big:
+    bigfont
@@ -0,0 +1,7 @@
+; A label used before it is defined, whose address depends on an align in between
+start:
+    JP end
+    db end - start
+    align 8
+end:
+    JP end
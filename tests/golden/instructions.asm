@@ -0,0 +1,36 @@
+; Every core CHIP-8 instruction form
+start:
+    CLS
+    RET
+    JP start
+    CALL start
+    SE V1, 0x12
+    SNE V2, 0x34
+    SE V3, V4
+    LD V5, 0x56
+    ADD V6, 0x78
+    LD V7, V8
+    OR V9, VA
+    AND VB, VC
+    XOR VD, VE
+    ADD V0, V1
+    SUB V2, V3
+    SHR V4, V5
+    SUBN V6, V7
+    SHL V8, V9
+    SNE VA, VB
+    LD I, start
+    JP V0, start
+    RND VC, 0xFF
+    DRW V1, V2, 5
+    SKP V3
+    SKNP V4
+    LD V5, DT
+    LD V6, K
+    LD DT, V7
+    LD ST, V8
+    ADD I, V9
+    LD F, VA
+    LD B, VB
+    LD [I], VC
+    LD VD, [I]
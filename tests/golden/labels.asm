@@ -0,0 +1,13 @@
+; Labels used before and after their definition, and label arithmetic
+    JP main
+table:
+    db 1, 2, 3, 4
+table_end:
+main:
+    LD I, table
+    LD V0, table_end - table
+    CALL routine
+loop:
+    JP loop
+routine: LD V1, 2
+    RET
@@ -0,0 +1,5 @@
+; Includes resolve from the including file's directory, two directories deep
+    LD I, ship
+    DRW V0, V1, 3
+    JP draw
+include "nested/graphics/graphics.asm"
@@ -0,0 +1,6 @@
+; Included from tests/golden, includes relative to tests/golden/nested/graphics
+include "sprites/ship.asm"
+draw:
+    LD I, rock
+    DRW V2, V3, 2
+    JP draw
@@ -0,0 +1 @@
+rock: db 0x66, 0x99
@@ -0,0 +1,3 @@
+; Includes its sibling, which is only found relative to this file
+include "rock.asm"
+ship: db 0x18, 0x3C, 0x7E
@@ -0,0 +1,7 @@
+; Operands of the wrong kind, or too large for their field, are errors rather than mis-encoded
+    SKP 5
+    CALL V0
+    SCD 20
+    DRW V0, V1, 20
+    JP 0x1234
+    CALL 0x1000
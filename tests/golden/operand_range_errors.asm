@@ -0,0 +1,7 @@
+; Byte operands past 255 and immediates where a register belongs are rejected
+    SE V0, 300
+    SNE V1, 0x100
+    LD V2, 256
+    SKP 5
+    SKNP 0x10
+    SE 5, V0
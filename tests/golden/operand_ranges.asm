@@ -0,0 +1,7 @@
+; Byte operands at the ends of their range, and register operands
+    SE V0, 255
+    SNE V1, 0
+    SE V2, 'z'
+    SKP V3
+    SKNP VF
+    RND V4, 0xFF
@@ -0,0 +1,8 @@
+; Operands separated by commas, whitespace or both
+    DRW V0, V1, 5
+    DRW V0 V1 5
+    DRW	V0,	V1,	5
+    DRW V0 , V1 ,5
+    LD V2,3
+    LD V2 3
+    db 1 2, 3
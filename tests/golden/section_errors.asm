@@ -0,0 +1,7 @@
+; Sections must start after what comes before them and be written 'section NAME at ADDR'
+section code at 0x300
+    CLS
+section data at 0x200
+    db 1
+section misc
+section db at 0x400
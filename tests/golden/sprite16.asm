@@ -0,0 +1,4 @@
+; A 16x16 Super-CHIP sprite, each row emitted as two bytes, high byte first
+box:
+    sprite16 0xFFFF, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001,
+        0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, 0x8001, %1111111100000000
@@ -0,0 +1,6 @@
+; Text and char literals are ASCII, anything else would be cut down to its low byte
+    text "cafe"
+    text "café"
+    text "it’s", "ok"
+    db 'é'
+    db '\xE9'
@@ -0,0 +1,9 @@
+; times repeats the line after its count, which can be an expression
+define N 3
+fill:
+    times 4 db 0xFF
+    TIMES  N + 1   db 1, 2
+    times N - 2 CLS
+    times (1+1) db 3
+    times 2 * 2 db 4
+    times (N + 1) / 2 - 1 db 5
@@ -0,0 +1,7 @@
+; a bad repeated line is reported once, not for every copy
+    times 3 FROB V0
+    times 2 + db 1
+    times
+    times 3
+    times 100000000 db 0
+    times (1 + 1 db 0
@@ -0,0 +1,12 @@
+; Typed defines holding values in range
+define byte LIMIT 200
+define nibble ROWS 5
+define reg POS V3
+define addr SPRITE sprite
+define word BIG 0xFFFF
+    LD POS, LIMIT
+    LD I, SPRITE
+    DRW V0, V1, ROWS
+    JP BIG - 0xF000
+sprite:
+    db 0xFF
@@ -0,0 +1,8 @@
+; Typed defines whose values are out of range for their kind
+define byte LIMIT 300
+define nibble ROWS 16
+define reg POS V3
+define POS 5
+define addr TOP 0x1000
+define count N 1
+    LD V0, 1
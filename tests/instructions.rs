@@ -0,0 +1,193 @@
+//! Encodes instructions and checks the operands each one rejects.
+
+mod common;
+
+use chip8_assembler::instructions::supported_instructions;
+use chip8_assembler::options::{AssemblerOptions, ShiftQuirk, Target};
+use chip8_assembler::report::instruction_list;
+
+#[test]
+fn jump_relative_to_v0() {
+    let source = ["    JP V0, table", "table:", "    db 1"];
+    assert_eq!(common::assemble(&source), [0xB2, 0x02, 1]);
+
+    let source = ["    JP V1, table", "table:", "    db 1"];
+    let errors = common::errors(&source, &AssemblerOptions::default());
+    assert_eq!(errors, ["JP Vx, addr only supports V0"]);
+}
+
+#[test]
+fn shift_quirks() {
+    let quirk = |quirk| AssemblerOptions {
+        shift_quirk: Some(quirk),
+        ..AssemblerOptions::default()
+    };
+    let source = ["    SHR V1", "    SHL V1, V2"];
+
+    let (_, bytes, _) = common::assemble_with(&source, &quirk(ShiftQuirk::Modern));
+    assert_eq!(bytes, [0x81, 0x06, 0x81, 0x2E]);
+    assert_eq!(
+        common::warnings(&source, &quirk(ShiftQuirk::Modern)),
+        ["SHL ignores Vy with the modern shift quirk, use SHL Vx"]
+    );
+    assert!(common::errors(&source, &quirk(ShiftQuirk::Modern)).is_empty());
+
+    assert_eq!(
+        common::errors(&source, &quirk(ShiftQuirk::Legacy)),
+        ["SHR needs Vy with the legacy shift quirk: SHR Vx, Vy"]
+    );
+    assert!(common::warnings(&source, &quirk(ShiftQuirk::Legacy)).is_empty());
+}
+
+#[test]
+fn nop_and_mov() {
+    assert_eq!(common::assemble(&["    NOP"]), [0x80, 0x00]);
+    assert_eq!(
+        common::assemble(&["    MOV V1, V2"]),
+        common::assemble(&["    LD V1, V2"])
+    );
+}
+
+#[test]
+fn supported_instructions_are_listed() {
+    let arities = |mnemonic: &str| -> Vec<usize> {
+        let mut arities: Vec<usize> = supported_instructions()
+            .iter()
+            .filter(|i| i.mnemonic == mnemonic)
+            .map(|i| i.arity())
+            .collect();
+        arities.sort();
+        arities.dedup();
+        arities
+    };
+    assert_eq!(arities("LD"), [2, 3]);
+    assert_eq!(arities("DRW"), [3]);
+
+    let list = instruction_list();
+    assert!(
+        list.contains("\nLD        Vx, byte        6xkk       chip8\n"),
+        "{}",
+        list
+    );
+    assert!(
+        list.contains("\nDRW       Vx, Vy, nibble  Dxyn       chip8\n"),
+        "{}",
+        list
+    );
+}
+
+#[test]
+fn defines_as_register_aliases() {
+    for register in ["V3", "v3"] {
+        let define = format!("define POS {}", register);
+        let source = [
+            define.as_str(),
+            "    LD POS, 5",
+            "    ADD V1, POS",
+            "    LD POS, V2",
+        ];
+        assert_eq!(
+            common::assemble(&source),
+            [0x63, 0x05, 0x81, 0x34, 0x83, 0x20],
+            "{}",
+            register
+        );
+    }
+}
+
+#[test]
+fn register_names() {
+    let source = ["    LD v1, V1", "    LD VA, vf", "    LD Va, 1"];
+    assert_eq!(
+        common::assemble(&source),
+        [0x81, 0x10, 0x8A, 0xF0, 0x6A, 0x01]
+    );
+    assert_eq!(
+        common::errors(&["    LD Vv1, 1"], &AssemblerOptions::default()),
+        ["LD: Invalid register 'Vv1', expected V0 to VF"]
+    );
+}
+
+#[test]
+fn ld_i_address_fits_12_bits() {
+    assert_eq!(common::assemble(&["    LD I, 0xFFF"]), [0xAF, 0xFF]);
+
+    let options = AssemblerOptions::default();
+    assert_eq!(
+        common::errors(&["    LD I, 0x1000"], &options),
+        ["LD I: address 0x1000 exceeds 12 bits"]
+    );
+    let source = ["define BIG 0x1000", "    LD I, BIG"];
+    assert_eq!(
+        common::errors(&source, &options),
+        ["LD I: address 0x1000 exceeds 12 bits, from 'BIG'"]
+    );
+}
+
+#[test]
+fn tabs_and_irregular_spacing() {
+    let expected = common::assemble(&["    LD V0, 5"]);
+    for line in [
+        "\tLD\tV0,\t5",
+        "    LD V0 , 5",
+        "    LD V0,5",
+        "  LD   V0 ,   5  ",
+    ] {
+        assert_eq!(common::assemble(&[line]), expected, "{:?}", line);
+    }
+}
+
+#[test]
+fn errors_explain_themselves() {
+    let options = AssemblerOptions::default();
+    let cases = [
+        (
+            "    LD V0, K, 3",
+            "LD: unsupported operand combination Vx, K, imm with 3 operands",
+        ),
+        ("    CLS V0", "CLS takes no operands, got 1"),
+        ("    DRW V0, V1", "DRW expects 3 operands, found 2"),
+        ("    SE 5, 5", "SE operand 1 must be a register, found 5"),
+    ];
+    for (line, message) in cases {
+        assert_eq!(common::errors(&[line], &options), [message], "{}", line);
+    }
+}
+
+#[test]
+fn every_form_encodes_as_listed() {
+    let options = AssemblerOptions {
+        target: Target::XoChip,
+        ..AssemblerOptions::default()
+    };
+    for info in supported_instructions() {
+        // Operand values that leave each field recognisable in the encoding
+        let operands: Vec<&str> = info
+            .form
+            .split(", ")
+            .filter(|o| !o.is_empty())
+            .map(|o| match o {
+                "Vx" => "VA",
+                "Vy" => "VB",
+                "byte" => "0xCD",
+                "nibble" => "3",
+                "addr" if info.size() == 4 => "0x1234",
+                "addr" => "0x123",
+                keyword => keyword,
+            })
+            .collect();
+        let line = format!("    {} {}", info.mnemonic, operands.join(", "));
+        let expected = info
+            .opcode
+            .replace(' ', "")
+            .replace('x', "A")
+            .replace('y', "B")
+            .replace("kk", "CD")
+            .replace("nnnn", "1234")
+            .replace("nnn", "123")
+            .replace('n', "3");
+        let bytes = common::assemble_ok(&[&line], &options);
+        let encoded: String = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+        assert_eq!(encoded, expected, "{}", line);
+    }
+}
@@ -0,0 +1,85 @@
+//! Encodes every form of LD, and checks no two forms of a mnemonic can match the same operands.
+
+mod common;
+
+use chip8_assembler::instructions::conflicting_forms;
+use chip8_assembler::options::{AssemblerOptions, Target};
+
+fn assemble(source: &[&str]) -> Vec<u8> {
+    common::assemble_ok(source, &AssemblerOptions::for_target(Target::XoChip))
+}
+
+#[test]
+fn every_ld_form() {
+    let forms: &[(&str, &[u8])] = &[
+        ("LD V1, 0x23", &[0x61, 0x23]),
+        ("LD V1, V2", &[0x81, 0x20]),
+        ("LD I, 0x345", &[0xA3, 0x45]),
+        ("LD V1, DT", &[0xF1, 0x07]),
+        ("LD V1, K", &[0xF1, 0x0A]),
+        ("LD DT, V1", &[0xF1, 0x15]),
+        ("LD ST, V1", &[0xF1, 0x18]),
+        ("LD F, V1", &[0xF1, 0x29]),
+        ("LD B, V1", &[0xF1, 0x33]),
+        ("LD [I], V1", &[0xF1, 0x55]),
+        ("LD V1, [I]", &[0xF1, 0x65]),
+        ("LD I, V1, V2", &[0x51, 0x22]),
+        ("LD V1, V2, I", &[0x51, 0x23]),
+        ("LD HF, V1", &[0xF1, 0x30]),
+        ("LD R, V1", &[0xF1, 0x75]),
+        ("LD V1, R", &[0xF1, 0x85]),
+        ("LD I, LONG, 0x1234", &[0xF0, 0x00, 0x12, 0x34]),
+        ("LD [I], audio", &[0xF0, 0x02]),
+    ];
+    for (line, bytes) in forms {
+        assert_eq!(assemble(&[&format!("    {}", line)]), *bytes, "{}", line);
+    }
+}
+
+#[test]
+fn keywords_in_any_case() {
+    assert_eq!(assemble(&["    ld v1, dt"]), [0xF1, 0x07]);
+    assert_eq!(assemble(&["    ld i, v1, v2"]), [0x51, 0x22]);
+    // The address word of a LONG load is laid out too, so labels after it stay put
+    assert_eq!(
+        assemble(&["    ld i, long target", "target:", "    jp target"]),
+        [0xF0, 0x00, 0x02, 0x04, 0x12, 0x04]
+    );
+}
+
+#[test]
+fn no_conflicting_forms() {
+    let conflicts: Vec<String> = conflicting_forms()
+        .iter()
+        .map(|(a, b)| format!("{} / {}", a, b))
+        .collect();
+    assert!(conflicts.is_empty(), "{:?}", conflicts);
+}
+
+#[test]
+fn indirect_i_in_any_case_and_spacing() {
+    for operand in ["[I]", "[i]", "[ I ]", "[I ]", "[ i]"] {
+        assert_eq!(
+            assemble(&[&format!("    LD V3, {}", operand)]),
+            [0xF3, 0x65]
+        );
+        assert_eq!(
+            assemble(&[&format!("    LD {}, V3", operand)]),
+            [0xF3, 0x55]
+        );
+    }
+}
+
+#[test]
+fn range_save_and_load_need_xochip() {
+    for target in [Target::Chip8, Target::SChip] {
+        let options = AssemblerOptions::for_target(target);
+        assert_eq!(
+            common::errors(&["    LD I, V1, V2", "    LD V1, V2, I"], &options),
+            [
+                "LD I, Vx, Vy requires --target xochip",
+                "LD Vx, Vy, I requires --target xochip"
+            ]
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Drives the assembler with source lines held in memory.
+
+use chip8_assembler::asm::{assemble_lines, AsmEnum};
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+
+fn lines(source: &[&str]) -> Vec<String> {
+    source.iter().map(|l| l.to_string()).collect()
+}
+
+#[test]
+fn assembles_lines_from_memory() {
+    let source = lines(&[
+        "define COUNT 3",
+        "start:",
+        "    LD V0, COUNT ; comments work as usual",
+        "    JP start",
+    ]);
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source,
+        "memory.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    assert_eq!(bytes, [0x60, 0x03, 0x12, 0x00]);
+    assert_eq!(assembly.files, ["memory.asm"]);
+    assert_eq!(assembly.symbols["start"].defined_at.file, "memory.asm");
+}
+
+#[test]
+fn includes_are_errors() {
+    let source = lines(&["    CLS", "include \"other.asm\""]);
+    let mut diagnostics = Diagnostics::new();
+    assemble_lines(
+        source,
+        "memory.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let messages: Vec<&str> = diagnostics
+        .items
+        .iter()
+        .map(|d| d.message.as_str())
+        .collect();
+    assert_eq!(
+        messages,
+        ["Cannot include other.asm when assembling lines from memory"]
+    );
+}
+
+#[test]
+fn label_directive_and_comment_on_one_line() {
+    let source = lines(&["data: db 1, 2 ; note", "colon: db ':' ; a: b"]);
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source,
+        "memory.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    assert_eq!(bytes, [1, 2, b':']);
+
+    let entries: Vec<String> = assembly
+        .instructions
+        .iter()
+        .map(|(entry, ..)| match entry {
+            AsmEnum::Label(l) => format!("label {}", l.name),
+            AsmEnum::Directive(d) => format!("{} {}", d.mnemonic, d.args.join(", ")),
+            _ => "other".to_string(),
+        })
+        .collect();
+    assert_eq!(entries, ["label data", "db 1, 2", "label colon", "db ':'"]);
+}
+
+#[test]
+fn to_bytes_twice_is_identical() {
+    let source = lines(&[
+        "define N 2",
+        "start:",
+        "    LD V0, N",
+        "    JP start",
+        "    db end - start",
+        "end:",
+    ]);
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source,
+        "memory.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let first = assembly.to_bytes(&mut diagnostics);
+    let second = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    assert_eq!(first, [0x60, 0x02, 0x12, 0x00, 0x05]);
+    assert_eq!(first, second);
+}
+
+#[test]
+fn iterates_resolved_entries() {
+    let source = lines(&["start:", "    CLS", "    db 1, 2, 3", "    JP start"]);
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source,
+        "memory.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    let entries: Vec<(usize, usize, &[u8])> = assembly
+        .iter_resolved(&bytes)
+        .map(|e| (e.offset, e.source_span.line, e.bytes))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            (0x200, 1, &[][..]),
+            (0x200, 2, &[0x00, 0xE0][..]),
+            (0x202, 3, &[1, 2, 3][..]),
+            (0x205, 4, &[0x12, 0x00][..]),
+        ]
+    );
+    let labelled = assembly.iter_resolved(&bytes).next().unwrap();
+    assert!(matches!(labelled.kind, AsmEnum::Label(l) if l.name == "start"));
+}
@@ -0,0 +1,39 @@
+//! Checks how the assembly listing shows data.
+
+use chip8_assembler::asm::assemble_lines;
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+use chip8_assembler::report::listing;
+
+/// Listing of `source` with and without packed words
+fn listings(source: &[&str]) -> (String, String) {
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source.iter().map(|l| l.to_string()),
+        "listing.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    (
+        listing(&assembly, &bytes, false),
+        listing(&assembly, &bytes, true),
+    )
+}
+
+#[test]
+fn packed_words() {
+    let (bytes, words) = listings(&["table:", "    db 0x12, 0x34, 0x56, 0x78"]);
+    assert!(bytes.contains("0x0200  12 34 56 78 "), "{}", bytes);
+    assert!(words.contains("0x0200  1234 5678 "), "{}", words);
+    assert!(words.contains("table:"), "{}", words);
+}
+
+#[test]
+fn instructions_are_not_packed() {
+    let (bytes, words) = listings(&["    CLS", "    db 1, 2, 3"]);
+    assert!(words.contains("0x0200  00E0 "), "{}", words);
+    assert!(words.contains("0x0202  0102 03 "), "{}", words);
+    assert_eq!(bytes.lines().next(), words.lines().next());
+}
@@ -0,0 +1,120 @@
+//! Assembles small programs with options the golden fixtures do not cover.
+
+mod common;
+
+use chip8_assembler::options::{AssemblerOptions, Target};
+use chip8_assembler::report::symbol_map;
+
+#[test]
+fn max_rom_size() {
+    let source = ["    CLS", "    db 1, 2, 3"];
+    let options = AssemblerOptions {
+        max_rom_size: Some(4),
+        ..AssemblerOptions::default()
+    };
+    assert_eq!(
+        common::errors(&source, &options),
+        ["Program is 5 bytes, 1 over the --max-rom-size of 4"]
+    );
+    let options = AssemblerOptions {
+        max_rom_size: Some(5),
+        ..AssemblerOptions::default()
+    };
+    assert!(common::errors(&source, &options).is_empty());
+}
+
+#[test]
+fn audio_pattern() {
+    let source = [
+        "    LD I, pattern",
+        "    LD [I], audio",
+        "pattern: audio %11110000, 0xFF, 1, 2, 3, 4, 5, 6,",
+        "    7, 8, 9, 10, 11, 12, 13, 14",
+    ];
+    let xochip = AssemblerOptions::for_target(Target::XoChip);
+    let bytes = common::assemble_ok(&source, &xochip);
+    assert_eq!(
+        bytes[4..],
+        [0xF0, 0xFF, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14]
+    );
+
+    assert_eq!(
+        common::errors(&["    audio 1, 2, 3"], &xochip),
+        ["audio needs 16 bytes, found 3"]
+    );
+    let schip = AssemblerOptions::for_target(Target::SChip);
+    let sixteen = ["    audio 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0"];
+    assert_eq!(
+        common::errors(&sixteen, &schip),
+        ["audio requires --target xochip"]
+    );
+}
+
+#[test]
+fn entry_label() {
+    let source = ["table:", "    db 1, 2", "main:", "    CLS", "    JP main"];
+    let options = AssemblerOptions {
+        entry: Some("start".to_string()),
+        ..AssemblerOptions::default()
+    };
+    assert_eq!(
+        common::errors(&source, &options),
+        ["Entry label 'start' is not defined"]
+    );
+
+    let options = AssemblerOptions {
+        entry: Some("main".to_string()),
+        ..AssemblerOptions::default()
+    };
+    let (assembly, _, diagnostics) = common::assemble_with(&source, &options);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    assert_eq!(
+        symbol_map(&assembly),
+        "address,name,flags\n0x0200,table,\n0x0202,main,entry\n"
+    );
+
+    // With a jump in front, the program runs from main rather than the table
+    let options = AssemblerOptions {
+        entry_jump: true,
+        ..options
+    };
+    assert_eq!(
+        common::assemble_ok(&source, &options),
+        [0x12, 0x04, 1, 2, 0x00, 0xE0, 0x12, 0x04]
+    );
+}
+
+#[test]
+fn fill_byte() {
+    let source = [
+        "    db 1",
+        "    align 2",
+        "    db 2",
+        "    offset 2",
+        "    db 3",
+        "section data at 0x208",
+        "    db 4",
+    ];
+    let options = AssemblerOptions {
+        fill: 0xFF,
+        ..AssemblerOptions::default()
+    };
+    assert_eq!(
+        common::assemble_ok(&source, &options),
+        [1, 0xFF, 2, 0xFF, 0xFF, 3, 0xFF, 0xFF, 4]
+    );
+}
+
+#[test]
+fn offset_symbol() {
+    let options = AssemblerOptions {
+        offset: 0x600,
+        offset_symbol: Some("__base".to_string()),
+        ..AssemblerOptions::default()
+    };
+    let source = ["    LD I, __base", "    LD I, __base + 2"];
+    assert_eq!(
+        common::assemble_ok(&source, &options),
+        [0xA6, 0x00, 0xA6, 0x02]
+    );
+}
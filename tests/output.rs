@@ -0,0 +1,71 @@
+//! Serializes assembled bytes in each output format.
+
+use chip8_assembler::output::{to_coe, to_mif, to_readmemh, ChecksumKind, Format, WordWidth};
+
+const ROM: [u8; 4] = [0x00, 0xE0, 0x12, 0x00];
+
+#[test]
+fn coe_and_mif() {
+    assert_eq!(
+        to_coe(&ROM),
+        "\
+; CHIP-8 program, 4 bytes
+memory_initialization_radix=16;
+memory_initialization_vector=
+00,
+E0,
+12,
+00;
+"
+    );
+    assert_eq!(
+        to_mif(&ROM),
+        "\
+-- CHIP-8 program, 4 bytes
+DEPTH = 4;
+WIDTH = 8;
+ADDRESS_RADIX = HEX;
+DATA_RADIX = HEX;
+CONTENT
+BEGIN
+0000 : 00;
+0001 : E0;
+0002 : 12;
+0003 : 00;
+END;
+"
+    );
+}
+
+#[test]
+fn readmemh_in_bytes_and_words() {
+    assert_eq!(to_readmemh(&ROM, WordWidth::Byte), "00\nE0\n12\n00\n");
+    assert_eq!(to_readmemh(&ROM, WordWidth::Word), "00E0\n1200\n");
+    // An odd byte is padded out to a word
+    assert_eq!(to_readmemh(&ROM[..3], WordWidth::Word), "00E0\n1200\n");
+}
+
+#[test]
+fn checksum_footer() {
+    let mut rom = ROM.to_vec();
+    ChecksumKind::Sum16.append(&mut rom);
+    assert_eq!(rom, [0x00, 0xE0, 0x12, 0x00, 0x00, 0xF2]);
+    assert!(ChecksumKind::Sum16.verify(&rom).is_ok());
+
+    // The CRC-16/CCITT-FALSE check value
+    let mut rom = b"123456789".to_vec();
+    ChecksumKind::Crc16.append(&mut rom);
+    assert_eq!(rom[9..], [0x29, 0xB1]);
+    assert!(ChecksumKind::Crc16.verify(&rom).is_ok());
+
+    rom[0] ^= 1;
+    assert!(ChecksumKind::Crc16.verify(&rom).is_err());
+}
+
+#[test]
+fn format_from_the_output_extension() {
+    assert_eq!(Format::from_extension("rom.hex"), Some(Format::IntelHex));
+    assert_eq!(Format::from_extension("ROM.H"), Some(Format::CArray(None)));
+    assert_eq!(Format::from_extension("rom.ch8"), None);
+    assert_eq!(Format::from_extension("rom"), None);
+}
@@ -0,0 +1,68 @@
+//! Renames symbols in a copy of a small program and checks the rewritten source.
+
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use chip8_assembler::asm::{generate_full_asm, Assembly};
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+use chip8_assembler::rename::rename;
+
+const SOURCE: &str = "\
+; Jumps to loop twice, loop stays in this comment
+    JP loop
+    db 1, 2
+loop:
+    LD V0, loop - 0x200 ; and here
+    JP loop
+";
+
+/// Writes `source` to a file of its own in the temp directory
+fn write_source(name: &str, source: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("chip8-rename-{}-{}", name, std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("main.asm");
+    fs::write(&path, source).unwrap();
+    path
+}
+
+fn assemble(path: &Path) -> (Assembly, Vec<u8>) {
+    let mut diagnostics = Diagnostics::new();
+    let assembly = generate_full_asm(
+        &[path.to_string_lossy().into_owned()],
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+    (assembly, bytes)
+}
+
+#[test]
+fn renamed_label_assembles_identically() {
+    let path = write_source("label", SOURCE);
+    let (assembly, bytes) = assemble(&path);
+
+    let rewritten = rename(&assembly, "loop", "again").unwrap();
+    let source = &rewritten[&path.to_string_lossy().into_owned()];
+    assert_eq!(source.matches("again").count(), 4);
+    assert!(source.contains("loop stays in this comment"));
+    fs::write(&path, source).unwrap();
+
+    let (renamed, renamed_bytes) = assemble(&path);
+    assert_eq!(renamed_bytes, bytes);
+    assert!(renamed.symbols.contains_key("again"));
+    assert!(!renamed.symbols.contains_key("loop"));
+}
+
+#[test]
+fn rename_rejects_collisions() {
+    let path = write_source("collision", "define other 1\nstart:\n    JP start\n");
+    let (assembly, _) = assemble(&path);
+
+    assert!(rename(&assembly, "start", "other").is_err());
+    assert!(rename(&assembly, "start", "JP").is_err());
+    assert!(rename(&assembly, "start", "v3").is_err());
+    assert!(rename(&assembly, "missing", "fine").is_err());
+}
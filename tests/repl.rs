@@ -0,0 +1,26 @@
+//! Drives the REPL with lines held in memory.
+
+use chip8_assembler::options::AssemblerOptions;
+use chip8_assembler::repl;
+
+fn session(input: &str) -> String {
+    let mut output = Vec::new();
+    repl::run(input.as_bytes(), &mut output, &AssemblerOptions::default()).unwrap();
+    String::from_utf8(output).unwrap()
+}
+
+#[test]
+fn defines_carry_over_to_later_lines() {
+    assert_eq!(
+        session("define SPEED 3\nLD V0, SPEED\n"),
+        "> > 0x0200: 60 03  01100000 00000011\n> \n"
+    );
+}
+
+#[test]
+fn a_line_with_errors_is_forgotten() {
+    assert_eq!(
+        session("FROB V0\nCLS\n"),
+        "> error: Unknown mnemonic 'FROB'\n> 0x0200: 00 e0  00000000 11100000\n> \n"
+    );
+}
@@ -0,0 +1,185 @@
+//! Reports printed about an assembled program.
+
+mod common;
+
+use chip8_assembler::options::{AssemblerOptions, Target};
+use chip8_assembler::report;
+
+#[test]
+fn register_reads_and_writes_per_routine() {
+    let source = [
+        "start:",
+        "    LD V0, 1",
+        "    ADD V0, V1",
+        "    LD [I], V2",
+        "    LD [I], audio",
+        "draw:",
+        "    LD V3, [I]",
+        "    DRW V0, V1, 5",
+        "    RET",
+    ];
+    let options = AssemblerOptions::for_target(Target::XoChip);
+    let (assembly, _, _) = common::assemble_with(&source, &options);
+    assert_eq!(
+        report::register_usage(&assembly),
+        "\
+Routine  Reads       Writes
+start    V0, V1, V2  V0, VF
+draw     V0, V1      V0, V1, V2, V3, VF
+"
+    );
+
+    // An LD missing its register is reported as an error, and reads nothing
+    let (assembly, _, _) = common::assemble_with(&["    LD [I]"], &options);
+    assert_eq!(
+        report::register_usage(&assembly),
+        "Routine  Reads  Writes\n0x0200   -      -\n"
+    );
+}
+
+#[test]
+fn xref_lists_every_use() {
+    let source = [
+        "define SPEED 2",
+        "loop:",
+        "    ADD V0, SPEED",
+        "    SE V0, 0",
+        "    JP loop",
+        "    JP loop",
+    ];
+    let (assembly, _, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        report::xref(&assembly),
+        "\
+Symbol  Kind    Defined     References
+SPEED   define  test.asm:1  test.asm:3
+loop    label   test.asm:2  test.asm:5, test.asm:6
+"
+    );
+}
+
+#[test]
+fn sprite_preview() {
+    let source = [
+        "    CLS",
+        "arrow:",
+        "    db %00011000",
+        "    db %00111100",
+        "    db %11111111",
+    ];
+    let (assembly, _, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        report::sprites(&assembly),
+        "0x0202 arrow (test.asm:3)\n   ##\n  ####\n########\n"
+    );
+}
+
+#[test]
+fn memory_map_of_code_data_and_padding() {
+    let source = [
+        "start:",
+        "    CLS",
+        "    JP start",
+        "table:",
+        "    db 1, 2, 3",
+        "    offset 3",
+        "end:",
+        "    RET",
+    ];
+    let (assembly, _, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        report::memory_map(&assembly),
+        "\
+Range          Size  Kind      Labels
+0x0200-0x0203  4     code      start
+0x0204-0x0206  3     data      table
+0x0207-0x0209  3     reserved
+0x020a-0x020b  2     code      end
+"
+    );
+}
+
+#[test]
+fn source_map_points_at_lines() {
+    let source = ["    CLS", "; comment", "loop:", "    JP loop"];
+    let (assembly, _, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        report::source_map(&assembly),
+        "address,size,file,line\n0x0200,2,test.asm,1\n0x0202,2,test.asm,4\n"
+    );
+    assert_eq!(assembly.span_at(0x200).unwrap().line, 1);
+}
+
+#[test]
+fn control_flow_of_a_loop() {
+    let source = [
+        "start:",
+        "    LD V0, 0",
+        "loop:",
+        "    ADD V0, 1",
+        "    SE V0, 10",
+        "    JP loop",
+        "    JP start",
+    ];
+    let (assembly, _, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        report::control_flow_dot(&assembly),
+        r#"digraph cfg {
+    node [shape=box, fontname=monospace];
+    b0200 [label="start (0x0200)\lLD V0, 0\l"];
+    b0202 [label="loop (0x0202)\lADD V0, 1\lSE V0, 10\l"];
+    b0206 [label="0x0206\lJP 514\l"];
+    b0208 [label="0x0208\lJP 512\l"];
+    b0200 -> b0202;
+    b0202 -> b0206;
+    b0202 -> b0208 [label="skip"];
+    b0206 -> b0202;
+    b0208 -> b0200;
+}
+"#
+    );
+}
+
+#[test]
+fn stats_count_instructions() {
+    let source = [
+        "define N 3",
+        "start:",
+        "    LD V0, N",
+        "    LD V1, 2",
+        "    JP start",
+        "data:",
+        "    db 1, 2, 3",
+    ];
+    let (assembly, _, _) = common::assemble_with(&source, &AssemblerOptions::default());
+    assert_eq!(
+        report::stats(&assembly),
+        "\
+Total bytes: 9
+Code bytes: 6
+Data bytes: 3
+Reserved bytes: 0
+Instructions: 3
+Labels: 2
+Defines: 1
+
+Mnemonic  Count
+LD        2
+JP        1
+"
+    );
+}
+
+#[test]
+fn diff_finds_the_changed_instruction() {
+    let options = AssemblerOptions::default();
+    let (old, old_bytes, _) =
+        common::assemble_with(&["    CLS", "    LD V0, 1", "    RET"], &options);
+    let (new, new_bytes, _) =
+        common::assemble_with(&["    CLS", "    LD V0, 2", "    RET"], &options);
+    assert_eq!(
+        report::diff(&old, &old_bytes, &new, &new_bytes),
+        "0x0203: 01 -> 02  (test.asm:2 -> test.asm:2)\n"
+    );
+    assert_eq!(report::diff(&old, &old_bytes, &old, &old_bytes), "");
+}
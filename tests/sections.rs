@@ -0,0 +1,48 @@
+//! Lays out programs split into sections with their own base addresses.
+
+use chip8_assembler::asm::{assemble_lines, AsmEnum};
+use chip8_assembler::diagnostics::Diagnostics;
+use chip8_assembler::options::AssemblerOptions;
+
+#[test]
+fn labels_follow_section_bases() {
+    let source = [
+        "section code at 0x200",
+        "start:",
+        "    LD I, sprite",
+        "    DRW V0, V1, 2",
+        "    JP start",
+        "section data at 0x400",
+        "sprite:",
+        "    db 0xFF, 0x81",
+        "after:",
+    ];
+    let mut diagnostics = Diagnostics::new();
+    let assembly = assemble_lines(
+        source.iter().map(|l| l.to_string()),
+        "sections.asm",
+        &AssemblerOptions::default(),
+        &mut diagnostics,
+    );
+    let bytes = assembly.to_bytes(&mut diagnostics);
+    assert!(!diagnostics.has_errors(), "{:?}", diagnostics.items);
+
+    let labels: Vec<(&str, usize)> = assembly
+        .instructions
+        .iter()
+        .filter_map(|(entry, offset, _)| match entry {
+            AsmEnum::Label(l) => Some((l.name.as_str(), *offset)),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        labels,
+        [("start", 0x200), ("sprite", 0x400), ("after", 0x402)]
+    );
+
+    // The gap between the sections is padded with zeros
+    assert_eq!(bytes.len(), 0x202);
+    assert_eq!(bytes[..6], [0xA4, 0x00, 0xD0, 0x12, 0x12, 0x00]);
+    assert!(bytes[6..0x200].iter().all(|&b| b == 0));
+    assert_eq!(bytes[0x200..], [0xFF, 0x81]);
+}
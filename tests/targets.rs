@@ -0,0 +1,48 @@
+//! Assembles the same source for each target, which accept different instructions.
+
+mod common;
+
+use chip8_assembler::options::{AssemblerOptions, Target};
+
+#[test]
+fn schip_instructions_per_target() {
+    let source = [
+        "    SCR",
+        "    SCD 2",
+        "    LOW",
+        "    HIGH",
+        "    LD HF, V1",
+    ];
+    let expected = [0x00, 0xFB, 0x00, 0xC2, 0x00, 0xFE, 0x00, 0xFF, 0xF1, 0x30];
+    for target in [Target::SChip, Target::XoChip] {
+        let options = AssemblerOptions::for_target(target);
+        assert_eq!(common::assemble_ok(&source, &options), expected);
+    }
+
+    let errors = common::errors(&source, &AssemblerOptions::for_target(Target::Chip8));
+    assert_eq!(errors.len(), source.len(), "{:?}", errors);
+    assert_eq!(errors[0], "SCR requires --target schip");
+}
+
+#[test]
+fn target_sets_memory_size_and_shift_quirk() {
+    // Past 4KB once loaded at 0x200, which only XO-CHIP has room for
+    let source = ["    offset 0xE00", "    CLS"];
+    assert_eq!(
+        common::errors(&source, &AssemblerOptions::for_target(Target::SChip)),
+        ["Program ends at 0x1002, past the 4096 bytes of memory on --target schip"]
+    );
+    let bytes = common::assemble_ok(&source, &AssemblerOptions::for_target(Target::XoChip));
+    assert_eq!(bytes.len(), 0xE02);
+
+    // CHIP-8 shifts Vy into Vx, so the one operand form is an error, while Super-CHIP
+    // shifts in place and accepts it
+    let source = ["    SHR V1"];
+    let chip8 = AssemblerOptions::for_target(Target::Chip8);
+    assert_eq!(
+        common::errors(&source, &chip8),
+        ["SHR needs Vy with the legacy shift quirk: SHR Vx, Vy"]
+    );
+    let schip = AssemblerOptions::for_target(Target::SChip);
+    assert_eq!(common::assemble_ok(&source, &schip), [0x81, 0x06]);
+}
@@ -0,0 +1,69 @@
+//! Warnings about code that assembles but is probably a mistake.
+
+mod common;
+
+use chip8_assembler::options::AssemblerOptions;
+
+#[test]
+fn unused_define() {
+    // A label at the load address is where the program starts, so it is never unused
+    let source = [
+        "define USED 1",
+        "define UNUSED 2",
+        "start:",
+        "    LD V0, USED",
+    ];
+    let warnings = common::warnings(&source, &AssemblerOptions::default());
+    assert_eq!(warnings, ["Unused define 'UNUSED'"]);
+}
+
+#[test]
+fn sys_is_deprecated_but_still_encoded() {
+    let source = ["    SYS 0x200"];
+    let warnings = common::warnings(&source, &AssemblerOptions::default());
+    assert_eq!(
+        warnings,
+        ["SYS is ignored by modern interpreters, use CALL or remove it"]
+    );
+    assert_eq!(common::assemble(&source), [0x02, 0x00]);
+}
+
+#[test]
+fn unreachable_after_jump() {
+    let source = [
+        "start:",
+        "    JP start",
+        "    CLS",
+        "    RET",
+        "reached:",
+        "    CLS",
+        "    JP reached",
+    ];
+    // Only the first dead instruction is reported, and the label makes the rest reachable
+    let options = AssemblerOptions::default();
+    assert_eq!(common::warnings(&source, &options), ["Unreachable CLS"]);
+    assert_eq!(common::warned_lines(&source, &options, "Unreachable"), [3]);
+}
+
+#[test]
+fn draw_past_the_sprite() {
+    let source = [
+        "    LD I, ship",
+        "    DRW V0, V1, 5",
+        "    LD I, ship",
+        "    DRW V0, V1, 3",
+        "ship:",
+        "    db 0x18, 0x3C, 0xFF",
+    ];
+    let checked = AssemblerOptions {
+        check_sprites: true,
+        ..AssemblerOptions::default()
+    };
+    assert_eq!(
+        common::warnings(&source, &checked),
+        ["DRW reads 5 bytes from 'ship', which only has 3"]
+    );
+    assert_eq!(common::warned_lines(&source, &checked, "DRW reads"), [2]);
+    // The check is opt-in
+    assert!(common::warnings(&source, &AssemblerOptions::default()).is_empty());
+}
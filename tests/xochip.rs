@@ -0,0 +1,68 @@
+//! Encodes the XO-CHIP instructions, which other targets reject.
+
+mod common;
+
+use chip8_assembler::options::{AssemblerOptions, Target};
+
+fn xochip() -> AssemblerOptions {
+    AssemblerOptions::for_target(Target::XoChip)
+}
+
+#[test]
+fn plane() {
+    assert_eq!(
+        common::assemble_ok(&["    PLANE 3"], &xochip()),
+        [0xF3, 0x01]
+    );
+    assert_eq!(
+        common::assemble_ok(&["    PLANE 0"], &xochip()),
+        [0xF0, 0x01]
+    );
+}
+
+#[test]
+fn plane_above_3_is_an_error() {
+    let errors = common::errors(&["    PLANE 4"], &xochip());
+    assert_eq!(errors, ["PLANE mask out of range (0 to 3): 4"]);
+}
+
+#[test]
+fn long_i_load_is_four_bytes() {
+    let source = ["    LD I, LONG, data", "    CLS", "data:", "    db 1"];
+    assert_eq!(
+        common::assemble_ok(&source, &xochip()),
+        [0xF0, 0x00, 0x02, 0x06, 0x00, 0xE0, 0x01]
+    );
+}
+
+#[test]
+fn needs_xochip() {
+    let options = AssemblerOptions::for_target(Target::SChip);
+    for line in [
+        "    PLANE 3",
+        "    LD I, LONG, 0x1234",
+        "    SCU 4",
+        "    LD [I], audio",
+    ] {
+        let errors = common::errors(&[line], &options);
+        assert!(
+            matches!(errors.as_slice(), [e] if e.ends_with("requires --target xochip")),
+            "{}: {:?}",
+            line,
+            errors
+        );
+    }
+}
+
+#[test]
+fn scroll_up_and_audio_buffer() {
+    let source = ["    SCU 4", "    LD [I], audio"];
+    assert_eq!(
+        common::assemble_ok(&source, &xochip()),
+        [0x00, 0xD4, 0xF0, 0x02]
+    );
+    assert_eq!(
+        common::errors(&["    SCU 16"], &xochip()),
+        ["SCU operand 1 must be a nibble (0 to 15), found 16"]
+    );
+}